@@ -0,0 +1,66 @@
+//! Recording and replaying of curl's output, matched by method and URL.
+
+use crate::CurlError;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// The output of curl for a single request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct Interaction {
+    /// The HTTP method of the request.
+    pub(crate) method: String,
+    /// The URL of the request.
+    pub(crate) url: String,
+    /// The exit code of curl, `None` if it was terminated by a signal.
+    pub(crate) exit_code: Option<i32>,
+    /// What curl wrote to stdout.
+    #[serde(with = "base64_bytes")]
+    pub(crate) stdout: Vec<u8>,
+    /// What curl wrote to stderr.
+    #[serde(with = "base64_bytes")]
+    pub(crate) stderr: Vec<u8>,
+}
+
+/// Returns the recorded interaction matching `method` and `url`, if any.
+pub(crate) fn find(path: &str, method: &str, url: &str) -> Result<Option<Interaction>, CurlError> {
+    Ok(load(path)?
+        .into_iter()
+        .find(|interaction| interaction.method == method && interaction.url == url))
+}
+
+/// Appends `interaction` to the cassette at `path`, creating it if needed.
+pub(crate) fn record(path: &str, interaction: Interaction) -> Result<(), CurlError> {
+    let mut interactions = load(path)?;
+    interactions.push(interaction);
+    let json = serde_json::to_vec_pretty(&interactions).map_err(io::Error::other)?;
+    std::fs::write(path, json)?;
+    Ok(())
+}
+
+/// Reads the interactions recorded at `path`, none if the file doesn't exist.
+fn load(path: &str) -> Result<Vec<Interaction>, CurlError> {
+    match std::fs::read(path) {
+        Ok(json) => serde_json::from_slice(&json)
+            .map_err(|err| CurlError::InvalidInput(format!("invalid cassette {}: {}", path, err))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Serializes bytes as a base64 string.
+mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(bytes))
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(de::Error::custom)
+    }
+}
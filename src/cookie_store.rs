@@ -0,0 +1,223 @@
+//! An in-memory cookie store shared between requests.
+
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+#[derive(Debug, Clone)]
+struct Cookie {
+    /// The name of the cookie.
+    name: String,
+    /// The value of the cookie.
+    value: String,
+    /// The domain the cookie is sent to.
+    domain: String,
+    /// Whether the cookie is only sent to `domain` itself, not its subdomains.
+    host_only: bool,
+    /// The path prefix the cookie is sent to.
+    path: String,
+    /// Whether the cookie is only sent over HTTPS.
+    secure: bool,
+    /// When the cookie expires, or `None` for a session cookie.
+    expires: Option<SystemTime>,
+}
+
+/// Cookies captured from `Set-Cookie` response headers, sent back on later
+/// requests whose domain and path match.
+///
+/// # Example
+///
+/// ```
+/// use curl_wrapper::{Curl, CookieStore};
+/// use std::sync::{Arc, Mutex};
+///
+/// let store = Arc::new(Mutex::new(CookieStore::new()));
+/// let login = Curl::new("https://example.com/login").cookie_store(store.clone());
+/// let profile = Curl::new("https://example.com/profile").cookie_store(store);
+/// ```
+#[derive(Debug, Default)]
+pub struct CookieStore {
+    cookies: Vec<Cookie>,
+}
+
+impl CookieStore {
+    /// Create a new, empty `CookieStore`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the `Cookie` header value for a request to `url`, if any
+    /// stored cookie matches it.
+    pub fn cookie_header(&self, url: &str) -> Option<String> {
+        let url = Url::parse(url).ok()?;
+        let host = url.host_str()?.to_ascii_lowercase();
+        let now = SystemTime::now();
+        let pairs: Vec<String> = self
+            .cookies
+            .iter()
+            .filter(|cookie| cookie.expires.is_none_or(|expires| expires > now))
+            .filter(|cookie| {
+                let domain_matches = if cookie.host_only {
+                    host == cookie.domain
+                } else {
+                    domain_match(&host, &cookie.domain)
+                };
+                domain_matches
+                    && path_match(url.path(), &cookie.path)
+                    && (!cookie.secure || url.scheme() == "https")
+            })
+            .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+            .collect();
+        (!pairs.is_empty()).then(|| pairs.join("; "))
+    }
+
+    /// Returns the cookies in the Netscape cookie file format read by curl.
+    pub(crate) fn to_netscape(&self) -> String {
+        let flag = |set: bool| if set { "TRUE" } else { "FALSE" };
+        self.cookies
+            .iter()
+            .map(|cookie| {
+                let expires = cookie.expires.map_or(0, |expires| {
+                    expires
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(1, |since| since.as_secs().max(1))
+                });
+                let domain = if cookie.host_only {
+                    cookie.domain.clone()
+                } else {
+                    format!(".{}", cookie.domain)
+                };
+                format!(
+                    "{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                    domain,
+                    flag(!cookie.host_only),
+                    cookie.path,
+                    flag(cookie.secure),
+                    expires,
+                    cookie.name,
+                    cookie.value
+                )
+            })
+            .collect()
+    }
+
+    /// Stores the cookies from the `Set-Cookie` headers of a response to `url`.
+    pub fn store(&mut self, url: &str, headers: &[String]) {
+        let Ok(url) = Url::parse(url) else {
+            return;
+        };
+        let Some(host) = url.host_str().map(|host| host.to_ascii_lowercase()) else {
+            return;
+        };
+        for header in headers {
+            let Some((key, value)) = header.split_once(':') else {
+                continue;
+            };
+            if key.trim().eq_ignore_ascii_case("Set-Cookie") {
+                self.set_cookie(&host, url.path(), value.trim());
+            }
+        }
+    }
+
+    /// Parses a single `Set-Cookie` value received from `host` for `path`.
+    fn set_cookie(&mut self, host: &str, path: &str, set_cookie: &str) {
+        let mut parts = set_cookie.split(';');
+        let Some((name, value)) = parts.next().and_then(|pair| pair.split_once('=')) else {
+            return;
+        };
+        let mut cookie = Cookie {
+            name: name.trim().to_string(),
+            value: value.trim().to_string(),
+            domain: host.to_string(),
+            host_only: true,
+            path: default_path(path),
+            secure: false,
+            expires: None,
+        };
+        if cookie.name.is_empty() {
+            return;
+        }
+        let is_ip = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok();
+        let now = SystemTime::now();
+        let (mut max_age, mut expires) = (None, None);
+        for attribute in parts {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "domain" => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    // An IP address only matches itself (RFC 6265 5.1.3).
+                    if is_ip {
+                        if domain != host {
+                            return;
+                        }
+                        continue;
+                    }
+                    if !domain_match(host, &domain) {
+                        // A server can't set cookies for unrelated domains.
+                        return;
+                    }
+                    // Nor for a public suffix such as `com` or `co.uk`, shared
+                    // by unrelated sites (RFC 6265 5.3 step 5).
+                    if psl::suffix_str(&domain) == Some(domain.as_str()) {
+                        if domain != host {
+                            return;
+                        }
+                        continue;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "secure" => cookie.secure = true,
+                "expires" => expires = httpdate::parse_http_date(value).ok(),
+                "max-age" => {
+                    if let Ok(age) = value.parse::<i64>() {
+                        // A huge age that overflows is a session cookie.
+                        max_age = Some(match u64::try_from(age) {
+                            Ok(age) if age > 0 => now.checked_add(Duration::from_secs(age)),
+                            _ => Some(UNIX_EPOCH),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        // `Max-Age` wins over `Expires` (RFC 6265 5.3 step 3).
+        cookie.expires = max_age.unwrap_or(expires);
+        self.cookies.retain(|c| {
+            c.name != cookie.name || c.domain != cookie.domain || c.path != cookie.path
+        });
+        if cookie.expires.is_none_or(|expires| expires > now) {
+            self.cookies.push(cookie);
+        }
+    }
+}
+
+/// Whether `host` is `domain` or one of its subdomains (RFC 6265 5.1.3).
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain
+        || host
+            .strip_suffix(domain)
+            .is_some_and(|prefix| prefix.ends_with('.'))
+}
+
+/// Whether `request_path` is within `cookie_path` (RFC 6265 5.1.4).
+fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    match request_path.strip_prefix(cookie_path) {
+        Some(rest) => cookie_path.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+        None => false,
+    }
+}
+
+/// The default cookie path for a request to `path` (RFC 6265 5.1.4).
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(i) => path[..i].to_string(),
+    }
+}
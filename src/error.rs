@@ -0,0 +1,99 @@
+//! The error type returned when a request fails.
+
+use crate::CurlResponse;
+use std::time::Duration;
+use std::{error, fmt, io};
+
+#[derive(Debug)]
+pub enum CurlError {
+    /// curl couldn't be run, e.g. because the executable wasn't found.
+    Io(io::Error),
+    /// The request was rejected before running curl.
+    InvalidInput(String),
+    /// curl failed to perform the request, e.g. because the connection failed.
+    Curl {
+        /// The exit code of curl, `None` if it was terminated by a signal.
+        exit_code: Option<i32>,
+        /// The error message printed by curl.
+        message: String,
+        /// The response parsed from curl's output if its status line and
+        /// headers were received before curl failed, e.g. with exit code 18
+        /// (partial transfer) or 28 (timeout). Its `exit_code` is curl's.
+        response: Option<Box<CurlResponse>>,
+    },
+    /// The server returned an HTTP error status, with `fail_on_error(true)`.
+    Http {
+        /// The status code of the response.
+        status_code: u16,
+    },
+    /// No data was received for the duration set with `idle_timeout`.
+    IdleTimeout(Duration),
+    /// curl's output exceeded the limit set with `max_response_bytes`.
+    ResponseTooLarge(usize),
+    /// The response couldn't be parsed.
+    InvalidResponse(String),
+    /// The response is malformed, with `strict_parse(true)`.
+    ParseError {
+        /// The offset of the malformed part in curl's output.
+        offset: usize,
+        /// The start of the malformed part.
+        snippet: String,
+    },
+    /// The request was cancelled with `send_cancellable`.
+    Cancelled,
+    /// An element of a JSON response couldn't be deserialized.
+    #[cfg(feature = "json")]
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for CurlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CurlError::Io(err) => write!(f, "failed to run curl: {}", err),
+            CurlError::InvalidInput(message) => write!(f, "invalid request: {}", message),
+            CurlError::Curl {
+                exit_code: Some(code),
+                message,
+                ..
+            } => write!(f, "curl failed with exit code {}: {}", code, message),
+            CurlError::Curl {
+                exit_code: None,
+                message,
+                ..
+            } => write!(f, "curl was terminated: {}", message),
+            CurlError::Http { status_code } => {
+                write!(f, "server returned HTTP status {}", status_code)
+            }
+            CurlError::IdleTimeout(timeout) => {
+                write!(f, "no data received for {:?}", timeout)
+            }
+            CurlError::ResponseTooLarge(limit) => {
+                write!(f, "response larger than {} bytes", limit)
+            }
+            CurlError::InvalidResponse(message) => write!(f, "invalid response: {}", message),
+            CurlError::ParseError { offset, snippet } => {
+                write!(f, "malformed response at byte {}: {:?}", offset, snippet)
+            }
+            CurlError::Cancelled => write!(f, "the request was cancelled"),
+            #[cfg(feature = "json")]
+            CurlError::Json(err) => write!(f, "invalid JSON: {}", err),
+        }
+    }
+}
+
+impl error::Error for CurlError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            CurlError::Io(err) => Some(err),
+            #[cfg(feature = "json")]
+            CurlError::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CurlError {
+    fn from(err: io::Error) -> Self {
+        CurlError::Io(err)
+    }
+}
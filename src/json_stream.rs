@@ -0,0 +1,144 @@
+//! Incremental parsing of a JSON array streamed from curl's stdout.
+
+use crate::{line_stream, CurlError};
+use futures::stream::{self, Stream};
+use serde::de::DeserializeOwned;
+use std::collections::VecDeque;
+use tempfile::NamedTempFile;
+use tokio::io::AsyncReadExt;
+use tokio::process::{Child, ChildStdout};
+use tokio::task::JoinHandle;
+
+/// Splits the bytes of a JSON array into the bytes of its elements, without
+/// buffering more than the element being read.
+#[derive(Debug, Default)]
+struct ArraySplitter {
+    /// Whether the opening `[` was read.
+    started: bool,
+    /// Whether the closing `]` was read.
+    finished: bool,
+    /// The nesting depth of objects and arrays within the current element.
+    depth: usize,
+    /// Whether the current position is inside a string.
+    in_string: bool,
+    /// Whether the previous byte was a backslash inside a string.
+    escaped: bool,
+    /// The bytes of the current element.
+    element: Vec<u8>,
+}
+
+impl ArraySplitter {
+    /// Feeds `bytes` to the splitter, pushing each completed element to `elements`.
+    fn push(&mut self, bytes: &[u8], elements: &mut VecDeque<Vec<u8>>) -> Result<(), String> {
+        for &byte in bytes {
+            if self.finished || !self.started {
+                match byte {
+                    b if b.is_ascii_whitespace() => {}
+                    b'[' if !self.started => self.started = true,
+                    _ if self.finished => return Err("unexpected data after the array".to_string()),
+                    _ => return Err("the response is not a JSON array".to_string()),
+                }
+                continue;
+            }
+            if self.in_string {
+                match byte {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
+                self.element.push(byte);
+                continue;
+            }
+            match byte {
+                b',' | b']' if self.depth == 0 => {
+                    let is_empty = self.element.iter().all(u8::is_ascii_whitespace);
+                    if byte == b']' {
+                        self.finished = true;
+                    }
+                    if is_empty && byte == b',' {
+                        return Err("unexpected ',' in the array".to_string());
+                    }
+                    if !is_empty {
+                        elements.push_back(std::mem::take(&mut self.element));
+                    }
+                }
+                b'"' => {
+                    self.in_string = true;
+                    self.element.push(byte);
+                }
+                b'{' | b'[' => {
+                    self.depth += 1;
+                    self.element.push(byte);
+                }
+                b'}' | b']' => {
+                    self.depth = self.depth.saturating_sub(1);
+                    self.element.push(byte);
+                }
+                _ => self.element.push(byte),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The state of a JSON array stream.
+struct State {
+    child: Child,
+    stdout: ChildStdout,
+    stderr: JoinHandle<std::io::Result<Vec<u8>>>,
+    splitter: ArraySplitter,
+    elements: VecDeque<Vec<u8>>,
+    /// The cookie file curl reads, kept until the stream ends.
+    _jar: Option<NamedTempFile>,
+}
+
+/// Streams the elements of the JSON array written to the stdout of `child`.
+pub(crate) fn array_stream<T: DeserializeOwned>(
+    child: Result<(Child, Option<NamedTempFile>), CurlError>,
+) -> impl Stream<Item = Result<T, CurlError>> {
+    let state = child.map(|(mut child, jar)| {
+        let stdout = child.stdout.take().expect("stdout is piped");
+        State {
+            stderr: line_stream::read_stderr(&mut child),
+            child,
+            stdout,
+            splitter: ArraySplitter::default(),
+            elements: VecDeque::new(),
+            _jar: jar,
+        }
+    });
+    stream::unfold(Some(state), |state| async move {
+        let mut state = match state? {
+            Ok(state) => state,
+            Err(err) => return Some((Err(err), None)),
+        };
+        let mut buf = [0; 8192];
+        loop {
+            if let Some(element) = state.elements.pop_front() {
+                let item = serde_json::from_slice(&element).map_err(CurlError::Json);
+                return Some((item, Some(Ok(state))));
+            }
+            let read = match state.stdout.read(&mut buf).await {
+                Ok(0) => return finish(state).await.map(|err| (Err(err), None)),
+                Ok(read) => read,
+                Err(err) => return Some((Err(err.into()), None)),
+            };
+            if let Err(message) = state.splitter.push(&buf[..read], &mut state.elements) {
+                return Some((Err(CurlError::InvalidResponse(message)), None));
+            }
+        }
+    })
+}
+
+/// Waits for curl to exit, returning an error if it failed or the array was
+/// incomplete.
+async fn finish(mut state: State) -> Option<CurlError> {
+    match line_stream::wait(&mut state.child, &mut state.stderr).await {
+        Err(err) => Some(err),
+        Ok(()) if state.splitter.finished => None,
+        Ok(()) => Some(CurlError::InvalidResponse(
+            "the JSON array ended unexpectedly".to_string(),
+        )),
+    }
+}
@@ -1,48 +1,421 @@
 //! A simple wrapper around the curl command-line interface
 
+#[cfg(feature = "json")]
+mod cassette;
+mod cookie_store;
+mod error;
+#[cfg(feature = "json")]
+mod json_stream;
+mod line_stream;
+#[cfg(feature = "serde")]
+mod spec;
+mod stats;
+
+use base64::engine::general_purpose::{URL_SAFE, URL_SAFE_NO_PAD};
+use base64::Engine;
+pub use cookie_store::CookieStore;
+pub use error::CurlError;
 use regex::Regex;
+#[cfg(feature = "serde")]
+pub use spec::RequestSpec;
+pub use stats::{Stats, Summary};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::{IpAddr, Ipv4Addr};
+use std::process::Output;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::{fmt, io};
-use tokio::process::Command;
+use tempfile::NamedTempFile;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, Command};
+use tokio_util::sync::CancellationToken;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Method {
     GET,
     POST,
     PUT,
     DELETE,
+    HEAD,
+}
+
+impl Method {
+    /// The name of the method, as sent in the request line.
+    fn as_str(&self) -> &'static str {
+        match self {
+            Method::GET => "GET",
+            Method::POST => "POST",
+            Method::PUT => "PUT",
+            Method::DELETE => "DELETE",
+            Method::HEAD => "HEAD",
+        }
+    }
 }
 
 pub struct Curl;
 
-#[derive(Debug)]
+/// Settings shared by the requests it creates, see `Curl::with_defaults`.
+#[derive(Debug, Clone)]
+pub struct CurlDefaults {
+    /// The headers sent unless a request sets a header with the same name.
+    headers: Vec<String>,
+}
+
+/// How `send` handles a response body that isn't valid UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum InvalidUtf8Strategy {
+    /// Replace invalid sequences with `U+FFFD REPLACEMENT CHARACTER`.
+    #[default]
+    Lossy,
+    /// Fail with `CurlError::InvalidResponse`.
+    Error,
+    /// Remove invalid sequences.
+    Strip,
+}
+
+/// A TLS protocol version, see `CurlBuilder::tls_min` and `CurlBuilder::tls_max`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TlsVersion {
+    Tls1_0,
+    Tls1_1,
+    Tls1_2,
+    Tls1_3,
+}
+
+impl TlsVersion {
+    /// The version as written in curl's flags, e.g. `1.2`.
+    fn as_str(&self) -> &'static str {
+        match self {
+            TlsVersion::Tls1_0 => "1.0",
+            TlsVersion::Tls1_1 => "1.1",
+            TlsVersion::Tls1_2 => "1.2",
+            TlsVersion::Tls1_3 => "1.3",
+        }
+    }
+}
+
+/// The authentication scheme of a request, see `CurlBuilder::auth`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AuthMethod {
+    /// HTTP Basic authentication.
+    Basic,
+    /// HTTP Digest authentication.
+    Digest,
+    /// NTLM authentication.
+    Ntlm,
+    /// An OAuth 2.0 bearer token.
+    Bearer,
+}
+
+/// The body of a request.
+#[derive(Debug, Clone)]
+enum Body {
+    /// Sent with `-d`.
+    Text(String),
+    /// Written to curl's stdin and sent byte for byte.
+    Bytes(Vec<u8>),
+    /// Read by curl from the file at the path and sent byte for byte.
+    File(String),
+    /// Written to curl's stdin as it is generated, and sent with chunked
+    /// transfer encoding.
+    Chunks(ChunkSource),
+}
+
+/// The chunks of a body, taken by the first request that sends it.
+#[derive(Clone)]
+struct ChunkSource(Arc<Mutex<Option<Box<dyn Iterator<Item = String> + Send>>>>);
+
+impl fmt::Debug for ChunkSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let consumed = self.0.lock().unwrap().is_none();
+        write!(f, "ChunkSource {{ consumed: {} }}", consumed)
+    }
+}
+
+/// A source of deadlines, e.g. a framework's request context, from which
+/// `--max-time` is computed when the request is sent.
+pub trait Deadline: Send + Sync {
+    /// Returns the time left before the deadline, or `None` if there is none.
+    fn remaining(&self) -> Option<Duration>;
+}
+
+/// Transforms the URL of a request before it is sent.
+#[derive(Clone)]
+struct UrlResolver(Arc<dyn Fn(&str) -> String + Send + Sync>);
+
+impl fmt::Debug for UrlResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UrlResolver")
+    }
+}
+
+/// The deadline source of a request.
+#[derive(Clone)]
+struct DeadlineSource(Arc<dyn Deadline>);
+
+impl fmt::Debug for DeadlineSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DeadlineSource({:?})", self.0.remaining())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct CurlBuilder {
+    /// The curl executable to run.
+    curl_path: String,
     /// The URL to send the request to.
     url: String,
     /// The HTTP method to use.
     method: Option<Method>,
     /// The headers to send with the request.
     headers: Vec<String>,
+    /// The headers to send unless `headers` has one with the same name.
+    default_headers: Vec<String>,
+    /// The User-Agent to send, overriding any `User-Agent` header.
+    user_agent: Option<String>,
     /// The body to send with the request.
-    body: Option<String>,
+    body: Option<Body>,
+    /// Whether curl may wait for a `100 Continue` before sending the body.
+    expect_continue: bool,
     /// The proxy to use.
     proxy: Option<String>,
+    /// The hosts to connect to without a proxy.
+    no_proxy: Option<String>,
+    /// The proxy read by `from_env`, used unless a proxy is set.
+    env_proxy: Option<String>,
+    /// The hosts read by `from_env`, used unless `no_proxy` is set.
+    env_no_proxy: Option<String>,
+    /// The SOCKS5 proxy to use, resolving hostnames locally.
+    socks5: Option<String>,
+    /// The SOCKS5 proxy to use, resolving hostnames through the proxy.
+    socks5_hostname: Option<String>,
+    /// The environment variables set for curl.
+    env: Vec<(String, String)>,
+    /// Whether to remove the proxy environment variables of curl.
+    clear_proxy_env: bool,
     /// Whether to follow redirects.
     redirects: bool,
+    /// Whether plaintext HTTP URLs are allowed.
+    allow_http: bool,
     /// Whether to enable compression.
     compressed: bool,
+    /// The encodings to request, instead of all those curl supports.
+    compression_algorithms: Vec<String>,
+    /// Whether to keep the body as received on the wire, decoding it in Rust.
+    #[cfg(feature = "decode")]
+    capture_wire: bool,
+    /// Whether HTTP error statuses fail the request.
+    fail_on_error: bool,
     /// The network interface to use.
     interface: Option<String>,
+    /// The local port or range of ports to bind to.
+    local_port: Option<String>,
+    /// Whether to enable TCP keep-alive probes, curl's default if unset.
+    keep_alive: Option<bool>,
+    /// The idle time before the first TCP keep-alive probe.
+    keep_alive_time: Option<Duration>,
+    /// The file TLS sessions are loaded from and saved to.
+    tls_session_cache: Option<String>,
+    /// The minimum TLS version to use.
+    tls_min: Option<TlsVersion>,
+    /// The maximum TLS version to use.
+    tls_max: Option<TlsVersion>,
+    /// The CA certificates to verify the server with.
+    cacert: Option<String>,
+    /// The CA certificates read by `from_env`, used unless `cacert` is set.
+    env_cacert: Option<String>,
+    /// Whether to use TCP Fast Open.
+    tcp_fastopen: bool,
+    /// Whether to disable Nagle's algorithm, curl's default if unset.
+    tcp_nodelay: Option<bool>,
+    /// The Unix domain socket to connect through.
+    unix_socket: Option<String>,
+    /// The maximum transfer speed, e.g. `100K`.
+    limit_rate: Option<String>,
+    /// The maximum size of the response body curl accepts.
+    max_size: Option<u64>,
+    /// The number of bytes of output kept in memory before spilling to disk.
+    buffer_limit: Option<usize>,
+    /// The maximum size of curl's output before it is killed.
+    max_response_bytes: Option<usize>,
+    /// The file the body is written to instead of the response.
+    output_file: Option<String>,
+    /// Whether to create the missing directories of the output file.
+    create_dirs: bool,
+    /// Whether to hide curl's progress meter.
+    silent: bool,
+    /// The byte range to request, e.g. `0-99` or `100-`.
+    range: Option<String>,
+    /// The custom `host:port:addr` resolve entries.
+    resolve: Vec<String>,
+    /// Whether to refuse connecting to private, loopback and link-local
+    /// addresses.
+    block_private_ips: bool,
+    /// The time without receiving data after which the request is aborted.
+    idle_timeout: Option<Duration>,
+    /// The delay after which a second, hedged request is fired.
+    hedge: Option<Duration>,
+    /// Whether to collect timing metrics.
+    timing: bool,
+    /// The source of the deadline the request must complete by.
+    deadline: Option<DeadlineSource>,
+    /// The hook transforming the URL before the request is sent.
+    url_resolver: Option<UrlResolver>,
+    /// The cookie store shared with other requests.
+    cookie_store: Option<Arc<Mutex<CookieStore>>>,
+    /// Whether to capture curl's verbose trace.
+    verbose: bool,
+    /// How to handle a response body that isn't valid UTF-8.
+    invalid_utf8: InvalidUtf8Strategy,
+    /// The authentication scheme, user and password.
+    auth: Option<(AuthMethod, String, String)>,
+    /// Whether to fail on a response without a status line.
+    strict_parse: bool,
+    /// The `name=value` cookies to send with the request.
+    cookies: Vec<String>,
+    /// The file cookies are saved to after the request.
+    cookie_jar: Option<String>,
+    /// The file cookies are read from and sent with the request.
+    cookie_file: Option<String>,
+    /// Whether to reject input that could enable request smuggling.
+    safe_mode: bool,
+    /// Whether to rewrite header names to their canonical Title-Case.
+    canonicalize_headers: bool,
+    /// Whether to only use IPv4 addresses.
+    ipv4: bool,
+    /// Whether to only use IPv6 addresses.
+    ipv6: bool,
+    /// Extra arguments passed to curl before the URL.
+    raw_args: Vec<String>,
+    /// The file responses are recorded to and replayed from.
+    #[cfg(feature = "json")]
+    cassette: Option<String>,
 }
 
 pub struct CurlResponse {
     /// The status code of the response.
     pub status_code: u16,
+    /// The reason phrase of the status line, e.g. `Not Found`. Empty for
+    /// HTTP/2 and HTTP/3, which have none.
+    pub reason: String,
     /// The headers of the response.
     pub headers: Vec<String>,
+    /// The status code and headers of every response in curl's output, in
+    /// order, e.g. each hop of a redirect chain. The last one is the final
+    /// response.
+    pub history: Vec<(u16, Vec<String>)>,
     /// The body of the response.
     pub body: String,
+    /// The raw bytes of the body, without trimming or UTF-8 conversion.
+    pub body_bytes: Vec<u8>,
+    /// The timing metrics, if requested with `with_timing`.
+    pub timing: Option<Timing>,
+    /// The URL the response came from after following redirects, if
+    /// requested with `redirects(true)`.
+    pub effective_url: Option<String>,
+    /// The exit code of curl, or -1 if it was terminated by a signal. `send`
+    /// fails on non-zero exit codes, so this is `0` for the responses it
+    /// returns and for responses built with `new`; a response received
+    /// before curl failed is attached to `CurlError::Curl` with its code.
+    pub exit_code: i32,
+    /// curl's verbose trace, if requested with `verbose`. It contains
+    /// sensitive headers such as `Authorization` unredacted.
+    pub trace: Option<String>,
+    /// When curl was started. For responses built with `new`, when the
+    /// response was built.
+    pub started_at: SystemTime,
+    /// When curl exited. For responses built with `new`, when the response
+    /// was built.
+    pub finished_at: SystemTime,
+    /// The number of body bytes uploaded, as reported by curl. 0 for
+    /// responses built with `new`.
+    pub bytes_sent: u64,
+    /// The number of body bytes downloaded, as received on the wire, as
+    /// reported by curl. 0 for responses built with `new`.
+    pub bytes_received: u64,
+    /// The body as received on the wire, before decoding, if requested with
+    /// `capture_wire`.
+    #[cfg(feature = "decode")]
+    pub wire_bytes: Option<Vec<u8>>,
+    /// The output beyond the buffer limit.
+    spill: Option<NamedTempFile>,
+    /// Where the output stopped being a valid response, and what follows.
+    malformed: Option<(usize, String)>,
+    /// Whether the body was decoded from its `Content-Encoding`, by curl or
+    /// the `decode` feature.
+    decoded: bool,
+}
+
+/// Timing metrics of a request, each measured from the start of the request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timing {
+    /// The time until name resolution completed.
+    pub namelookup: Duration,
+    /// The time until the TCP connection was established.
+    pub connect: Duration,
+    /// The time until the TLS handshake completed, zero for plain HTTP.
+    pub appconnect: Duration,
+    /// The total time of the request.
+    pub total: Duration,
+}
+
+/// A parsed `Content-Range: bytes start-end/total` header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContentRange {
+    /// The first byte of the range.
+    pub start: u64,
+    /// The last byte of the range, inclusive.
+    pub end: u64,
+    /// The full size of the resource, `None` if unknown (`*`).
+    pub total: Option<u64>,
+}
+
+/// The security-relevant headers of a response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecurityHeaders {
+    /// The `Strict-Transport-Security` header.
+    pub strict_transport_security: Option<String>,
+    /// The `Content-Security-Policy` header.
+    pub content_security_policy: Option<String>,
+    /// The `X-Frame-Options` header.
+    pub x_frame_options: Option<String>,
+}
+
+/// The caching metadata of a response, from its `Cache-Control`, `Age`,
+/// `Expires` and `Date` headers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CachePolicy {
+    /// The `max-age` directive.
+    pub max_age: Option<Duration>,
+    /// Whether the `no-cache` directive is set: the response must be
+    /// revalidated before each use.
+    pub no_cache: bool,
+    /// Whether the `no-store` directive is set: the response must not be
+    /// stored.
+    pub no_store: bool,
+    /// Whether the `private` directive is set: the response must not be
+    /// stored by shared caches.
+    pub private: bool,
+    /// The `Age` header, zero if missing.
+    pub age: Duration,
+    /// The `Expires` header. An invalid date is in the past.
+    pub expires: Option<SystemTime>,
+    /// The `Date` header.
+    pub date: Option<SystemTime>,
 }
 
+/// Separates the `--write-out` variables from the response in curl's stdout.
+const WRITE_OUT_MARKER: &str = "\n--curl-wrapper-write-out--\n";
+
+/// The proxy environment variables honored by curl, in lowercase.
+const PROXY_ENV: [&str; 4] = ["http_proxy", "https_proxy", "all_proxy", "no_proxy"];
+
 impl Curl {
     /// Create a new `Curl` instance.
     ///
@@ -56,7 +429,7 @@ impl Curl {
     ///
     /// # Example
     ///
-    /// ```
+    /// ```no_run
     /// use curl_wrapper::Curl;
     /// use curl_wrapper::Method;
     ///
@@ -75,16 +448,236 @@ impl Curl {
     ///     println!("Output: {:?}", output);
     /// }
     /// ```
+    #[allow(clippy::new_ret_no_self)]
     pub fn new(url: &str) -> CurlBuilder {
         CurlBuilder {
+            curl_path: "curl".to_string(),
             url: url.to_string(),
             method: None,
             headers: Vec::new(),
+            default_headers: Vec::new(),
+            user_agent: None,
             body: None,
+            expect_continue: true,
             proxy: None,
+            no_proxy: None,
+            env_proxy: None,
+            env_no_proxy: None,
+            socks5: None,
+            socks5_hostname: None,
+            env: Vec::new(),
+            clear_proxy_env: false,
             redirects: false,
+            allow_http: true,
             compressed: false,
+            compression_algorithms: Vec::new(),
+            #[cfg(feature = "decode")]
+            capture_wire: false,
+            fail_on_error: false,
             interface: None,
+            local_port: None,
+            keep_alive: None,
+            keep_alive_time: None,
+            tls_session_cache: None,
+            tls_min: None,
+            tls_max: None,
+            cacert: None,
+            env_cacert: None,
+            tcp_fastopen: false,
+            tcp_nodelay: None,
+            unix_socket: None,
+            limit_rate: None,
+            max_size: None,
+            buffer_limit: None,
+            max_response_bytes: None,
+            output_file: None,
+            create_dirs: false,
+            silent: true,
+            range: None,
+            resolve: Vec::new(),
+            block_private_ips: false,
+            idle_timeout: None,
+            hedge: None,
+            timing: false,
+            deadline: None,
+            url_resolver: None,
+            cookie_store: None,
+            verbose: false,
+            invalid_utf8: InvalidUtf8Strategy::Lossy,
+            auth: None,
+            strict_parse: false,
+            cookies: Vec::new(),
+            cookie_jar: None,
+            cookie_file: None,
+            safe_mode: false,
+            canonicalize_headers: false,
+            ipv4: false,
+            ipv6: false,
+            raw_args: Vec::new(),
+            #[cfg(feature = "json")]
+            cassette: None,
+        }
+    }
+
+    /// Create a new `CurlBuilder` with the proxy and CA certificates set from
+    /// the environment variables used by curl and other HTTP clients:
+    ///
+    /// * the proxy from `HTTPS_PROXY` for `https` URLs or `http_proxy`
+    ///   otherwise, falling back to `ALL_PROXY`,
+    /// * the hosts bypassing it from `NO_PROXY`,
+    /// * the CA certificates from `CURL_CA_BUNDLE`, `REQUESTS_CA_BUNDLE` or
+    ///   `SSL_CERT_FILE`.
+    ///
+    /// Lowercase and uppercase names are both read, except for `http_proxy`:
+    /// like curl, uppercase `HTTP_PROXY` is ignored, since CGI servers set it
+    /// from the client's `Proxy` header. Builder calls such as `set_proxy`,
+    /// `socks5`, `no_proxy` and `cacert` override these settings.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::from_env("https://example.com");
+    /// ```
+    pub fn from_env(url: &str) -> CurlBuilder {
+        Curl::from_env_with(url, |name| std::env::var(name).ok())
+    }
+
+    /// Create a new `CurlBuilder` like `from_env`, reading the variables
+    /// with `var` instead of from the process environment.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::from_env_with("https://example.com", |name| {
+    ///     (name == "HTTPS_PROXY").then(|| "http://proxy.example.com:8080".to_string())
+    /// });
+    /// ```
+    pub fn from_env_with(url: &str, var: impl Fn(&str) -> Option<String>) -> CurlBuilder {
+        let var = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| var(name).filter(|value| !value.is_empty()))
+        };
+        let is_https = url
+            .get(..8)
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case("https://"));
+        let proxy = if is_https {
+            var(&["HTTPS_PROXY", "https_proxy"])
+        } else {
+            var(&["http_proxy"])
+        };
+        CurlBuilder {
+            env_proxy: proxy.or_else(|| var(&["ALL_PROXY", "all_proxy"])),
+            env_no_proxy: var(&["NO_PROXY", "no_proxy"]),
+            env_cacert: var(&["CURL_CA_BUNDLE", "REQUESTS_CA_BUNDLE", "SSL_CERT_FILE"]),
+            ..Curl::new(url)
+        }
+    }
+
+    /// Create a `CurlDefaults` whose requests send `headers`.
+    ///
+    /// A header set on a request with `set_header` replaces the default
+    /// headers with the same name, ignoring case, instead of being sent
+    /// alongside them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let api = Curl::with_defaults(vec!["Accept: application/json", "User-Agent: my-app/1.0"]);
+    /// let users = api.new("https://example.com/users");
+    /// let image = api.new("https://example.com/logo.png").set_header("Accept: image/png");
+    /// ```
+    pub fn with_defaults(headers: Vec<&str>) -> CurlDefaults {
+        CurlDefaults {
+            headers: headers.into_iter().map(str::to_string).collect(),
+        }
+    }
+
+    /// Sends a request to each of `urls` concurrently, with the settings of
+    /// `base`. The results are returned in the order of `urls`, and a failed
+    /// request doesn't affect the others.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use curl_wrapper::Curl;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let base = Curl::new("").set_header("Accept: application/json");
+    ///     let results = Curl::send_many(&base, ["https://example.com/a", "https://example.com/b"]).await;
+    ///     for result in results {
+    ///         println!("{:?}", result.map(|response| response.status_code));
+    ///     }
+    /// }
+    /// ```
+    pub async fn send_many<I>(base: &CurlBuilder, urls: I) -> Vec<Result<CurlResponse, CurlError>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let requests = urls.into_iter().map(|url| {
+            let mut curl = base.clone();
+            curl.url = url.as_ref().to_string();
+            async move { curl.send().await }
+        });
+        futures::future::join_all(requests).await
+    }
+
+    /// Sends `requests` concurrently, running at most `max_concurrency` curl
+    /// processes at a time. The results are returned in the order of
+    /// `requests`, and a failed request doesn't affect the others.
+    ///
+    /// Fails with `CurlError::InvalidInput` if `max_concurrency` is 0.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use curl_wrapper::Curl;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let requests = (0..100)
+    ///         .map(|i| Curl::new(&format!("https://example.com/items/{}", i)))
+    ///         .collect();
+    ///     let results = Curl::send_all_limited(requests, 8).await.unwrap();
+    ///     println!("{} responses", results.iter().filter(|r| r.is_ok()).count());
+    /// }
+    /// ```
+    pub async fn send_all_limited(
+        requests: Vec<CurlBuilder>,
+        max_concurrency: usize,
+    ) -> Result<Vec<Result<CurlResponse, CurlError>>, CurlError> {
+        if max_concurrency == 0 {
+            return Err(CurlError::InvalidInput(
+                "max_concurrency must be at least 1".to_string(),
+            ));
+        }
+        let semaphore = tokio::sync::Semaphore::new(max_concurrency);
+        let requests = requests.iter().map(|curl| async {
+            let _permit = semaphore
+                .acquire()
+                .await
+                .expect("semaphore is never closed");
+            curl.send().await
+        });
+        Ok(futures::future::join_all(requests).await)
+    }
+}
+
+impl CurlDefaults {
+    /// Create a new `CurlBuilder` for `url` with the default settings.
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(&self, url: &str) -> CurlBuilder {
+        CurlBuilder {
+            default_headers: self.headers.clone(),
+            ..Curl::new(url)
         }
     }
 }
@@ -106,8 +699,37 @@ impl CurlBuilder {
         self
     }
 
+    /// Appends `segment` to the path of the URL, percent-encoded and
+    /// separated by a single slash, keeping the query string. An invalid URL
+    /// is left unchanged, and fails when the request is sent.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com/api/?page=2")
+    ///     .push_path("users")
+    ///     .push_path("a b");
+    /// assert!(curl.to_command_string().unwrap().contains("https://example.com/api/users/a%20b?page=2"));
+    /// ```
+    pub fn push_path(mut self, segment: &str) -> Self {
+        if let Ok(mut url) = url::Url::parse(&self.url) {
+            if let Ok(mut segments) = url.path_segments_mut() {
+                segments.pop_if_empty().push(segment);
+            }
+            self.url = url.to_string();
+        }
+        self
+    }
+
     /// Sets the HTTP headers for the request.
     ///
+    /// The header must have the form `Name: value`, or `Name;` to send an
+    /// empty value; `send` fails with `CurlError::InvalidInput` otherwise.
+    /// Each call adds a header, so a name can be sent several times, in the
+    /// order the headers were set; use `replace_header` to replace it.
+    ///
     /// # Example
     ///
     /// ```
@@ -139,7 +761,8 @@ impl CurlBuilder {
         self
     }
 
-    /// Sets the HTTP body for the request.
+    /// Sets the header `name` to `value`, removing the headers with the same
+    /// name, ignoring case, set before.
     ///
     /// # Example
     ///
@@ -147,14 +770,22 @@ impl CurlBuilder {
     /// use curl_wrapper::Curl;
     ///
     /// let curl = Curl::new("https://example.com")
-    ///     .set_body("Hello, World!");
+    ///     .set_header("Accept: text/html")
+    ///     .replace_header("Accept", "application/json");
     /// ```
-    pub fn set_body(mut self, body: &str) -> Self {
-        self.body = Some(body.to_string());
+    pub fn replace_header(mut self, name: &str, value: &str) -> Self {
+        self.headers
+            .retain(|header| !header_name(header).trim().eq_ignore_ascii_case(name));
+        if value.is_empty() {
+            self.headers.push(format!("{};", name));
+        } else {
+            self.headers.push(format!("{}: {}", name, value));
+        }
         self
     }
 
-    /// Sets the HTTP proxy for the request.
+    /// Sets the User-Agent for the request. It takes precedence over a
+    /// `User-Agent` header set with `set_header`, which is then not sent.
     ///
     /// # Example
     ///
@@ -162,14 +793,33 @@ impl CurlBuilder {
     /// use curl_wrapper::Curl;
     ///
     /// let curl = Curl::new("https://example.com")
-    ///     .set_proxy("http://proxy.example.com:8080");
+    ///     .user_agent("my-app/1.0");
     /// ```
-    pub fn set_proxy(mut self, proxy: &str) -> Self {
-        self.proxy = Some(proxy.to_string());
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    /// Set the authentication scheme and credentials of the request. For
+    /// `AuthMethod::Bearer`, `pass` is the token and `user` is ignored.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::{AuthMethod, Curl};
+    ///
+    /// let curl = Curl::new("https://example.com").auth(AuthMethod::Digest, "user", "secret");
+    /// ```
+    pub fn auth(mut self, method: AuthMethod, user: &str, pass: &str) -> Self {
+        self.auth = Some((method, user.to_string(), pass.to_string()));
         self
     }
 
-    /// Enables or disables redirects for the request.
+    /// Enables or disables rejecting requests that could enable HTTP request
+    /// smuggling before curl is run: CR, LF or NUL characters in the URL,
+    /// headers or cookies, `Transfer-Encoding` combined with
+    /// `Content-Length`, conflicting `Content-Length` headers, and a `GET` or
+    /// `HEAD` request with a body, which some servers and proxies ignore.
     ///
     /// # Example
     ///
@@ -177,14 +827,18 @@ impl CurlBuilder {
     /// use curl_wrapper::Curl;
     ///
     /// let curl = Curl::new("https://example.com")
-    ///     .redirects(true);
+    ///     .set_header("X-Injected: a\r\nContent-Length: 0")
+    ///     .safe_mode(true);
+    /// assert!(curl.build_args().is_err());
     /// ```
-    pub fn redirects(mut self, r: bool) -> Self {
-        self.redirects = r;
+    pub fn safe_mode(mut self, safe: bool) -> Self {
+        self.safe_mode = safe;
         self
     }
 
-    /// Enables or disables compression for the request.
+    /// Enables or disables rewriting header names to their canonical
+    /// Title-Case form (e.g. `content-type` to `Content-Type`) before sending.
+    /// Header values are left untouched.
     ///
     /// # Example
     ///
@@ -192,14 +846,27 @@ impl CurlBuilder {
     /// use curl_wrapper::Curl;
     ///
     /// let curl = Curl::new("https://example.com")
-    ///     .compressed(true);
+    ///     .set_header("content-type: application/json")
+    ///     .canonicalize_headers(true);
     /// ```
-    pub fn compressed(mut self, compress: bool) -> Self {
-        self.compressed = compress;
+    pub fn canonicalize_headers(mut self, canonicalize: bool) -> Self {
+        self.canonicalize_headers = canonicalize;
         self
     }
 
-    /// Enables or disables interface for the request.
+    /// Sets the HTTP body for the request.
+    ///
+    /// In-memory bodies are sent with a `Content-Length` header, never
+    /// chunked. curl computes it for each request, so it isn't repeated on
+    /// a redirect that drops the body.
+    ///
+    /// The body is sent with curl's `-d`, which reads the body from a file
+    /// instead if it starts with `@`, stripping CR and LF characters from it;
+    /// use `set_body_binary` to always send the body unmodified.
+    ///
+    /// The body is sent with any method, including the default `GET`, so set
+    /// the method with `method`. `safe_mode` rejects a `GET` or `HEAD` with a
+    /// body.
     ///
     /// # Example
     ///
@@ -207,197 +874,5233 @@ impl CurlBuilder {
     /// use curl_wrapper::Curl;
     ///
     /// let curl = Curl::new("https://example.com")
-    ///     .interface("eth0");
+    ///     .set_body("Hello, World!");
     /// ```
-    pub fn interface(mut self, interface: &str) -> Self {
-        self.interface = Some(interface.to_string());
+    pub fn set_body(mut self, body: &str) -> Self {
+        self.body = Some(Body::Text(body.to_string()));
         self
     }
 
-    /// Executes the request and returns the output.
+    /// Sets the HTTP body for the request, sent byte for byte with curl's
+    /// `--data-binary`, even if it starts with `@`.
     ///
     /// # Example
     ///
     /// ```
     /// use curl_wrapper::Curl;
     ///
-    /// #[tokio::main]
-    /// async fn main() {
-    ///     let curl = Curl::new("https://example.com")
-    ///         .interface("eth0");
-    ///     let output = curl.send().await.unwrap();
-    ///     println!("Output: {:?}", output);
-    /// }
+    /// let curl = Curl::new("https://example.com")
+    ///     .set_body_binary("line1\nline2");
     /// ```
-    pub async fn send(&self) -> Result<CurlResponse, io::Error> {
-        let mut curl = Command::new("curl");
-        curl.arg("--silent");
-        curl.arg("--include");
-
-        if let Some(interface) = &self.interface {
-            curl.arg("--interface").arg(interface);
-        }
-
-        if self.redirects {
-            curl.arg("-L");
-        }
-
-        match &self.method {
-            Some(Method::GET) => curl.arg("-X").arg("GET"),
-            Some(Method::POST) => curl.arg("-X").arg("POST"),
-            Some(Method::PUT) => curl.arg("-X").arg("PUT"),
-            Some(Method::DELETE) => curl.arg("-X").arg("DELETE"),
-            None => curl.arg("-X").arg("GET"),
-        };
-
-        if let Some(proxy) = &self.proxy {
-            curl.arg("--proxy").arg(proxy);
-        }
-
-        curl.arg(&self.url);
-
-        for i in &self.headers {
-            curl.arg("-H").arg(i);
-        }
-
-        if let Some(body) = &self.body {
-            curl.arg("-d").arg(body);
-        }
-
-        if self.compressed {
-            curl.arg("--compressed");
-        }
+    pub fn set_body_binary(self, body: &str) -> Self {
+        self.body_bytes(body.as_bytes().to_vec())
+    }
 
-        let output = curl.output().await?;
-        Ok(CurlResponse::new(output.stdout))
+    /// Sets the HTTP body to raw bytes, fed to curl through stdin and sent
+    /// unmodified.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .body_bytes(vec![0x89, 0x50, 0x4e, 0x47]);
+    /// ```
+    pub fn body_bytes(mut self, body: Vec<u8>) -> Self {
+        self.body = Some(Body::Bytes(body));
+        self
     }
-}
 
-impl fmt::Debug for CurlResponse {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "CurlResponse {{ status_code: {}, headers: {:?}, body: {:?} }}",
-            self.status_code, self.headers, self.body
-        )
+    /// Sets the HTTP body to the contents of the file at `path`, read by curl
+    /// and sent unmodified.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .body_from_file("upload.bin");
+    /// ```
+    pub fn body_from_file(mut self, path: &str) -> Self {
+        self.body = Some(Body::File(path.to_string()));
+        self
     }
-}
 
-impl CurlResponse {
-    /// Create a new CurlResponse from an Output.
+    /// Sets the HTTP body to the items of `iter`, written to curl's stdin as
+    /// they are generated and sent with chunked transfer encoding.
     ///
-    /// # Arguments
-    /// * `output` - The output of the curl command.
+    /// The iterator is consumed by the first send: sending the request again,
+    /// including from a clone of the builder, fails with
+    /// `CurlError::InvalidInput`. For the same reason, the request isn't
+    /// hedged.
     ///
-    /// # Returns
-    /// A new CurlResponse.
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::{Curl, Method};
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .method(Method::POST)
+    ///     .body_from_iter((0..1000).map(|i| format!("{}\n", i)));
+    /// ```
+    pub fn body_from_iter(mut self, iter: impl Iterator<Item = String> + Send + 'static) -> Self {
+        self.body = Some(Body::Chunks(ChunkSource(Arc::new(Mutex::new(Some(
+            Box::new(iter),
+        ))))));
+        self
+    }
+
+    /// Sets the HTTP body to `data` encoded as URL-safe base64 without padding.
     ///
     /// # Example
+    ///
     /// ```
-    /// use curl_wrapper::CurlResponse;
+    /// use curl_wrapper::Curl;
     ///
-    /// let output = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<html><body><h1>Hello, World!</h1></body></html>";
-    /// let response = CurlResponse::new(output.to_vec());
-    /// println!("Status Code: {}", response.status_code);
-    /// println!("Headers: {:?}", response.headers);
-    /// println!("Body: {:?}", response.body);
+    /// let curl = Curl::new("https://example.com")
+    ///     .set_body_base64(&[0xfb, 0xff]);
     /// ```
-    pub fn new(stdout: Vec<u8>) -> Self {
-        let raw_response = String::from_utf8_lossy(&stdout);
-        let blocks: Vec<&str> = raw_response.split("\r\n\r\n").collect();
-        let re = Regex::new(r"HTTP/.*?\s(\d{3})").unwrap();
-        let mut status_code = 0;
-        let mut headers = Vec::new();
-        let mut body = String::new();
-        for block in &blocks {
-            let capture = re.captures(block);
-            if capture.is_none() {
-                continue;
-            }
-            let code = capture.unwrap().get(1).unwrap();
-            if code.as_str().starts_with("3") {
-                continue;
-            }
-            status_code = code.as_str().parse().unwrap();
-            headers = block
-                .lines()
-                .skip(1)
-                .take_while(|line| !line.is_empty())
-                .map(|line| line.trim().to_string())
-                .collect();
-            body = blocks.last().unwrap().trim().to_string();
-            break;
-        }
-        CurlResponse {
-            status_code,
-            headers,
-            body,
-        }
+    pub fn set_body_base64(self, data: &[u8]) -> Self {
+        let body = URL_SAFE_NO_PAD.encode(data);
+        self.set_body(&body)
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// Sets the HTTP body to `data` encoded as URL-safe base64 with padding.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .set_body_base64_padded(&[0xfb, 0xff]);
+    /// ```
+    pub fn set_body_base64_padded(self, data: &[u8]) -> Self {
+        let body = URL_SAFE.encode(data);
+        self.set_body(&body)
+    }
+
+    /// Enables or disables the `Expect: 100-continue` header curl sends with
+    /// large bodies, waiting up to a second for the server's `100 Continue`
+    /// before sending the body. Disable it for servers that never send one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .body_bytes(vec![0; 1024 * 1024])
+    ///     .expect_continue(false);
+    /// ```
+    pub fn expect_continue(mut self, expect: bool) -> Self {
+        self.expect_continue = expect;
+        self
+    }
+
+    /// Sets the HTTP proxy for the request.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .set_proxy("http://proxy.example.com:8080");
+    /// ```
+    pub fn set_proxy(mut self, proxy: &str) -> Self {
+        self.proxy = Some(proxy.to_string());
+        self
+    }
+
+    /// Sets the comma-separated hosts to connect to without the proxy, or
+    /// `*` for all of them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .set_proxy("http://proxy.example.com:8080")
+    ///     .no_proxy("localhost,.internal.example.com");
+    /// ```
+    pub fn no_proxy(mut self, hosts: &str) -> Self {
+        self.no_proxy = Some(hosts.to_string());
+        self
+    }
+
+    /// Sets a SOCKS5 proxy for the request, resolving hostnames locally.
+    ///
+    /// Cannot be combined with `set_proxy` or `socks5h`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .socks5("127.0.0.1:1080");
+    /// ```
+    pub fn socks5(mut self, host_port: &str) -> Self {
+        self.socks5 = Some(host_port.to_string());
+        self
+    }
+
+    /// Sets a SOCKS5 proxy for the request, letting the proxy resolve hostnames.
+    ///
+    /// Cannot be combined with `set_proxy` or `socks5`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .socks5h("127.0.0.1:9050");
+    /// ```
+    pub fn socks5h(mut self, host_port: &str) -> Self {
+        self.socks5_hostname = Some(host_port.to_string());
+        self
+    }
+
+    /// Set an environment variable of the curl process.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .env("SSLKEYLOGFILE", "/tmp/keys.log");
+    /// ```
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Enables or disables removing the proxy environment variables curl
+    /// honors (`http_proxy`, `https_proxy`, `all_proxy`, `no_proxy` and their
+    /// uppercase forms), including those set with `env`, so that only the
+    /// proxies set on the builder are used.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com").clear_proxy_env(true);
+    /// ```
+    pub fn clear_proxy_env(mut self, clear: bool) -> Self {
+        self.clear_proxy_env = clear;
+        self
+    }
+
+    /// Enables or disables redirects for the request. When enabled, the final
+    /// URL is available in `CurlResponse::effective_url`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .redirects(true);
+    /// ```
+    pub fn redirects(mut self, r: bool) -> Self {
+        self.redirects = r;
+        self
+    }
+
+    /// Allows or forbids plaintext `http://` URLs, allowed by default. When
+    /// forbidden, `send` fails with `CurlError::InvalidInput` for an `http://`
+    /// URL, and curl refuses to follow redirects to anything but HTTPS.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .allow_http(false);
+    /// ```
+    pub fn allow_http(mut self, allow: bool) -> Self {
+        self.allow_http = allow;
+        self
+    }
+
+    /// Enables or disables failing the request on HTTP error statuses (4xx
+    /// and 5xx) with `CurlError::Http`, using curl's `--fail`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .fail_on_error(true);
+    /// ```
+    pub fn fail_on_error(mut self, fail: bool) -> Self {
+        self.fail_on_error = fail;
+        self
+    }
+
+    /// Enables or disables compression for the request.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .compressed(true);
+    /// ```
+    pub fn compressed(mut self, compress: bool) -> Self {
+        self.compressed = compress;
+        self
+    }
+
+    /// Requests only the given encodings, e.g. `["br"]`, in the
+    /// `Accept-Encoding` header. Implies `compressed(true)`, so the body is
+    /// still decoded.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .compression_algorithms(&["gzip"]);
+    /// ```
+    pub fn compression_algorithms(mut self, algorithms: &[&str]) -> Self {
+        self.compression_algorithms = algorithms.iter().map(|a| a.to_string()).collect();
+        self.compressed = true;
+        self
+    }
+
+    /// Keeps the body as received on the wire in `CurlResponse::wire_bytes`,
+    /// next to the decoded `body_bytes`. With `compressed(true)`, compression
+    /// is then requested with an `Accept-Encoding` header and the body is
+    /// decoded in Rust instead of by curl.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .compressed(true)
+    ///     .capture_wire(true);
+    /// ```
+    #[cfg(feature = "decode")]
+    pub fn capture_wire(mut self, capture_wire: bool) -> Self {
+        self.capture_wire = capture_wire;
+        self
+    }
+
+    /// Enables or disables interface for the request. It is passed to curl's
+    /// `--interface` unchanged, so it can also be an IP address, or a name
+    /// prefixed with `if!` (an interface) or `host!` (a host name or address).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .interface("eth0");
+    /// ```
+    pub fn interface(mut self, interface: &str) -> Self {
+        self.interface = Some(interface.to_string());
+        self
+    }
+
+    /// Set the local port, or range of ports such as `5000-5100`, the
+    /// connection is made from.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .local_port("5000-5100");
+    /// ```
+    pub fn local_port(mut self, range: &str) -> Self {
+        self.local_port = Some(range.to_string());
+        self
+    }
+
+    /// Enables or disables TCP keep-alive probes on the connection.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .keep_alive(false);
+    /// ```
+    pub fn keep_alive(mut self, keep_alive: bool) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Sets how long the connection must be idle before TCP keep-alive
+    /// probes are sent, in whole seconds.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    /// use std::time::Duration;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .keep_alive_time(Duration::from_secs(30));
+    /// ```
+    pub fn keep_alive_time(mut self, time: Duration) -> Self {
+        self.keep_alive_time = Some(time);
+        self
+    }
+
+    /// Loads TLS sessions from the file at `path` and saves them back after
+    /// the request, so that later requests to the same host, e.g. retries,
+    /// can resume the session instead of doing a full handshake.
+    ///
+    /// Requires curl 8.12 or later, built with SSL session export.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .tls_session_cache("/tmp/example-sessions");
+    /// ```
+    pub fn tls_session_cache(mut self, path: &str) -> Self {
+        self.tls_session_cache = Some(path.to_string());
+        self
+    }
+
+    /// Set the minimum TLS version to use.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::{Curl, TlsVersion};
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .tls_min(TlsVersion::Tls1_2);
+    /// ```
+    pub fn tls_min(mut self, version: TlsVersion) -> Self {
+        self.tls_min = Some(version);
+        self
+    }
+
+    /// Set the maximum TLS version to use. It can't be lower than the
+    /// version set with `tls_min`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::{Curl, TlsVersion};
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .tls_max(TlsVersion::Tls1_2);
+    /// ```
+    pub fn tls_max(mut self, version: TlsVersion) -> Self {
+        self.tls_max = Some(version);
+        self
+    }
+
+    /// Verifies the server with the CA certificates in the PEM file at
+    /// `path` instead of the system's.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .cacert("/etc/ssl/internal-ca.pem");
+    /// ```
+    pub fn cacert(mut self, path: &str) -> Self {
+        self.cacert = Some(path.to_string());
+        self
+    }
+
+    /// Enables or disables TCP Fast Open, which sends data in the opening
+    /// packet of the connection.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .tcp_fastopen(true);
+    /// ```
+    pub fn tcp_fastopen(mut self, tcp_fastopen: bool) -> Self {
+        self.tcp_fastopen = tcp_fastopen;
+        self
+    }
+
+    /// Enables or disables `TCP_NODELAY`. curl enables it by default, so
+    /// this is mostly useful to disable it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .tcp_nodelay(false);
+    /// ```
+    pub fn tcp_nodelay(mut self, tcp_nodelay: bool) -> Self {
+        self.tcp_nodelay = Some(tcp_nodelay);
+        self
+    }
+
+    /// Connects through the Unix domain socket at `path` instead of TCP,
+    /// e.g. to talk to a local daemon. The URL's host is only used for the
+    /// `Host` header.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("http://localhost/v1.43/containers/json")
+    ///     .unix_socket("/var/run/docker.sock");
+    /// ```
+    pub fn unix_socket(mut self, path: &str) -> Self {
+        self.unix_socket = Some(path.to_string());
+        self
+    }
+
+    /// Forces the request to resolve and connect over IPv4 only.
+    ///
+    /// Cannot be combined with `ipv6(true)`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .ipv4(true);
+    /// ```
+    pub fn ipv4(mut self, ipv4: bool) -> Self {
+        self.ipv4 = ipv4;
+        self
+    }
+
+    /// Forces the request to resolve and connect over IPv6 only.
+    ///
+    /// Cannot be combined with `ipv4(true)`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .ipv6(true);
+    /// ```
+    pub fn ipv6(mut self, ipv6: bool) -> Self {
+        self.ipv6 = ipv6;
+        self
+    }
+
+    /// Limits the transfer speed to `rate` bytes per second, with an optional
+    /// `K`, `M`, `G` or `T` suffix (e.g. `"200K"`, `"1M"`). An invalid rate
+    /// fails the request with `CurlError::InvalidInput`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .limit_rate("100K");
+    /// ```
+    pub fn limit_rate(mut self, rate: &str) -> Self {
+        self.limit_rate = Some(rate.to_string());
+        self
+    }
+
+    /// Aborts the request if the response body is larger than `bytes`, using
+    /// curl's `--max-filesize`. `send` then fails with `CurlError::Curl` and
+    /// exit code 63.
+    ///
+    /// The size is only checked against the `Content-Length` header, so a
+    /// response without one (e.g. chunked) isn't limited.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .max_size(10 * 1024 * 1024);
+    /// ```
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Keeps at most `bytes` of the body in memory and writes the rest to a
+    /// temporary file, deleted with the response. The headers of every
+    /// response, including redirects, are always kept in memory.
+    ///
+    /// `body` and `body_bytes` then only hold the part of the body kept in
+    /// memory and aren't decoded; read the whole body with
+    /// `CurlResponse::body_reader`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com/large.bin")
+    ///     .buffer_limit(1024 * 1024);
+    /// ```
+    pub fn buffer_limit(mut self, bytes: usize) -> Self {
+        self.buffer_limit = Some(bytes);
+        self
+    }
+
+    /// Kills curl and fails with `CurlError::ResponseTooLarge` once its
+    /// output, headers included, exceeds `bytes`, whatever the
+    /// `Content-Length` announced. Unlike `max_size`, this also stops
+    /// responses without a `Content-Length`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .max_response_bytes(10 * 1024 * 1024);
+    /// ```
+    pub fn max_response_bytes(mut self, bytes: usize) -> Self {
+        self.max_response_bytes = Some(bytes);
+        self
+    }
+
+    /// Writes the body to the file at `path` instead of keeping it in the
+    /// response, whose `body` is then empty. The status and headers are
+    /// still parsed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com/large.bin")
+    ///     .output_file("/tmp/large.bin");
+    /// ```
+    pub fn output_file(mut self, path: &str) -> Self {
+        self.output_file = Some(path.to_string());
+        self
+    }
+
+    /// Enables or disables creating the missing parent directories of the
+    /// file set with `output_file`, instead of failing.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com/large.bin")
+    ///     .output_file("/tmp/downloads/2024/large.bin")
+    ///     .create_dirs(true);
+    /// ```
+    pub fn create_dirs(mut self, create: bool) -> Self {
+        self.create_dirs = create;
+        self
+    }
+
+    /// Enables or disables hiding curl's progress meter, enabled by default.
+    /// When disabled, the progress meter is written to stderr and ends up in
+    /// the message of `CurlError::Curl`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com").silent(false);
+    /// ```
+    pub fn silent(mut self, silent: bool) -> Self {
+        self.silent = silent;
+        self
+    }
+
+    /// Requests only the bytes from `start` to `end` inclusive, or to the end
+    /// of the resource if `end` is `None`. A server honoring the range
+    /// responds with `206 Partial Content`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .range(0, Some(99));
+    /// ```
+    pub fn range(mut self, start: u64, end: Option<u64>) -> Self {
+        self.range = Some(match end {
+            Some(end) => format!("{}-{}", start, end),
+            None => format!("{}-", start),
+        });
+        self
+    }
+
+    /// Resolves `host` on `port` to `addr` instead of using DNS.
+    ///
+    /// Can be called multiple times to pin several hosts.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .resolve("example.com", 443, "127.0.0.1");
+    /// ```
+    pub fn resolve(mut self, host: &str, port: u16, addr: &str) -> Self {
+        self.resolve.push(format!("{}:{}:{}", host, port, addr));
+        self
+    }
+
+    /// Refuses to connect to private (RFC 1918 and IPv6 unique local),
+    /// loopback, link-local and unspecified addresses, to guard against
+    /// SSRF when fetching user-supplied URLs. `send` then fails with
+    /// `CurlError::InvalidInput`.
+    ///
+    /// The host is resolved before running curl, which is then pinned to the
+    /// checked address. Addresses set with `resolve` are checked instead.
+    /// Redirects and proxies would bypass the check, so combining this with
+    /// `redirects`, `set_proxy`, `socks5` or `socks5h` fails with
+    /// `CurlError::InvalidInput`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .block_private_ips(true);
+    /// ```
+    pub fn block_private_ips(mut self, block: bool) -> Self {
+        self.block_private_ips = block;
+        self
+    }
+
+    /// Aborts the request, killing curl, if no data is received for `timeout`,
+    /// regardless of curl's own timeouts. `send` then fails with
+    /// `CurlError::IdleTimeout`. This catches servers that trickle data.
+    ///
+    /// With `output_file`, data written to the file counts as received, so
+    /// a stall may take up to twice `timeout` to be noticed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    /// use std::time::Duration;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .idle_timeout(Duration::from_secs(10));
+    /// ```
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Fires a second identical request if the first one hasn't completed
+    /// after `after`, returning whichever finishes first and killing the other.
+    ///
+    /// Only use this for idempotent requests. Requests with a body set with
+    /// `body_from_iter`, which can only be sent once, aren't hedged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    /// use std::time::Duration;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .hedge(Duration::from_millis(200));
+    /// ```
+    pub fn hedge(mut self, after: Duration) -> Self {
+        self.hedge = Some(after);
+        self
+    }
+
+    /// Sets the curl executable to run, e.g. a specific build such as
+    /// curl-impersonate. Defaults to `curl` on the `PATH`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .curl_path("/usr/local/bin/curl_chrome116");
+    /// ```
+    pub fn curl_path(mut self, path: &str) -> Self {
+        self.curl_path = path.to_string();
+        self
+    }
+
+    /// Limits the request to the time remaining on `source`'s deadline,
+    /// computed when the request is sent.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::{Curl, Deadline};
+    /// use std::time::{Duration, Instant};
+    ///
+    /// struct Context {
+    ///     deadline: Instant,
+    /// }
+    ///
+    /// impl Deadline for Context {
+    ///     fn remaining(&self) -> Option<Duration> {
+    ///         Some(self.deadline.saturating_duration_since(Instant::now()))
+    ///     }
+    /// }
+    ///
+    /// let context = Context { deadline: Instant::now() + Duration::from_secs(5) };
+    /// let curl = Curl::new("https://example.com")
+    ///     .with_deadline_source(context);
+    /// ```
+    pub fn with_deadline_source(mut self, source: impl Deadline + 'static) -> Self {
+        self.deadline = Some(DeadlineSource(Arc::new(source)));
+        self
+    }
+
+    /// Transforms the URL with `resolver` right before the request is sent,
+    /// e.g. to map service names to hosts.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("service://users/profile")
+    ///     .url_resolver(|url| url.replace("service://users", "http://10.0.0.7:8080"));
+    /// ```
+    pub fn url_resolver(
+        mut self,
+        resolver: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.url_resolver = Some(UrlResolver(Arc::new(resolver)));
+        self
+    }
+
+    /// Returns the URL the request is sent to, after the URL resolver.
+    fn resolved_url(&self) -> String {
+        match &self.url_resolver {
+            Some(UrlResolver(resolver)) => resolver(&self.url),
+            None => self.url.clone(),
+        }
+    }
+
+    /// Enables or disables collecting timing metrics into `CurlResponse::timing`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .with_timing(true);
+    /// ```
+    pub fn with_timing(mut self, timing: bool) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// Attaches a cookie store shared with other requests. Matching cookies
+    /// from the store are sent with the request, and cookies set by the
+    /// response are saved into it.
+    ///
+    /// The store is handed to curl's cookie engine as a temporary cookie
+    /// file, so cookies set during a redirect chain are sent to its next
+    /// hops. It therefore doesn't show in `build_args`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::{Curl, CookieStore};
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let store = Arc::new(Mutex::new(CookieStore::new()));
+    /// let curl = Curl::new("https://example.com")
+    ///     .cookie_store(store);
+    /// ```
+    pub fn cookie_store(mut self, store: Arc<Mutex<CookieStore>>) -> Self {
+        self.cookie_store = Some(store);
+        self
+    }
+
+    /// Adds a cookie to send with the request. Can be called multiple times.
+    ///
+    /// The cookies are sent in the same `Cookie` header as those of a
+    /// `cookie_store`, but are dropped by curl if a `Cookie` header is set
+    /// with `set_header`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .cookie("session", "abc")
+    ///     .cookie("theme", "dark");
+    /// ```
+    pub fn cookie(mut self, name: &str, value: &str) -> Self {
+        self.cookies.push(format!("{}={}", name, value));
+        self
+    }
+
+    /// Saves the cookies received by the request to the file at `path`.
+    ///
+    /// Use the same path with `cookie_file` to keep a session across requests.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .cookie_jar("cookies.txt");
+    /// ```
+    pub fn cookie_jar(mut self, path: &str) -> Self {
+        self.cookie_jar = Some(path.to_string());
+        self
+    }
+
+    /// Sends the cookies stored in the file at `path` with the request.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .cookie_file("cookies.txt")
+    ///     .cookie_jar("cookies.txt");
+    /// ```
+    pub fn cookie_file(mut self, path: &str) -> Self {
+        self.cookie_file = Some(path.to_string());
+        self
+    }
+
+    /// Enables or disables capturing curl's verbose trace (`-v`) into
+    /// `CurlResponse::trace`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .verbose(true);
+    /// ```
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Sets how a response body that isn't valid UTF-8 is converted into
+    /// `CurlResponse::body`. `body_bytes` always keeps the raw bytes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::{Curl, InvalidUtf8Strategy};
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .on_invalid_utf8(InvalidUtf8Strategy::Error);
+    /// ```
+    pub fn on_invalid_utf8(mut self, strategy: InvalidUtf8Strategy) -> Self {
+        self.invalid_utf8 = strategy;
+        self
+    }
+
+    /// Enables or disables failing with `CurlError::ParseError` when the
+    /// output of curl has no status line or header boundary, instead of
+    /// returning a response with status code 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com").strict_parse(true);
+    /// ```
+    pub fn strict_parse(mut self, strict: bool) -> Self {
+        self.strict_parse = strict;
+        self
+    }
+
+    /// Passes `arg` to curl as is, before the URL, for flags without a
+    /// builder method. Each call adds a single argument, which is never
+    /// split or interpreted by a shell.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .raw_arg("--tlsv1.3");
+    /// ```
+    pub fn raw_arg(mut self, arg: &str) -> Self {
+        self.raw_args.push(arg.to_string());
+        self
+    }
+
+    /// Passes multiple arguments to curl as is, in order. See `raw_arg`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .raw_args(vec!["--retry", "3"]);
+    /// ```
+    pub fn raw_args(mut self, args: Vec<&str>) -> Self {
+        for arg in args {
+            self.raw_args.push(arg.to_string());
+        }
+        self
+    }
+
+    /// Records the output of curl to the JSON file at `path` the first time
+    /// the request is sent, and replays it without running curl afterwards.
+    /// Recordings are matched by method and URL.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let curl = Curl::new("https://example.com")
+    ///     .cassette("tests/fixtures/example.json");
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn cassette(mut self, path: &str) -> Self {
+        self.cassette = Some(path.to_string());
+        self
+    }
+
+    /// Executes the request and returns the output.
+    ///
+    /// Fails with `CurlError::InvalidInput` without running curl if the URL
+    /// isn't a valid absolute URL, with `CurlError::Curl` if curl exits with
+    /// an error, e.g. when the connection fails, and with `CurlError::Http`
+    /// if `fail_on_error` is enabled and the server returns an error status.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use curl_wrapper::Curl;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let curl = Curl::new("https://example.com")
+    ///         .interface("eth0");
+    ///     let output = curl.send().await.unwrap();
+    ///     println!("Output: {:?}", output);
+    /// }
+    /// ```
+    pub async fn send(&self) -> Result<CurlResponse, CurlError> {
+        let (args, _jar) = self.prepare(false).await?;
+        #[cfg(feature = "json")]
+        if let Some(path) = &self.cassette {
+            let method = self.method.as_ref().map_or("GET", Method::as_str);
+            let interaction = match cassette::find(path, method, &self.url)? {
+                Some(interaction) => interaction,
+                None => {
+                    let (mut output, spill) = self.output(&args).await?;
+                    if let Some(spill) = spill {
+                        // The recording must hold the whole body.
+                        output.stdout.extend(std::fs::read(spill.path())?);
+                    }
+                    let interaction = cassette::Interaction {
+                        method: method.to_string(),
+                        url: self.url.clone(),
+                        exit_code: output.status.code(),
+                        stdout: output.stdout,
+                        stderr: output.stderr,
+                    };
+                    cassette::record(path, interaction.clone())?;
+                    interaction
+                }
+            };
+            return self.response(
+                interaction.stdout,
+                &interaction.stderr,
+                interaction.exit_code,
+                None,
+            );
+        }
+        let started_at = SystemTime::now();
+        let (output, spill) = self.output(&args).await?;
+        let finished_at = SystemTime::now();
+        let mut response =
+            self.response(output.stdout, &output.stderr, output.status.code(), spill)?;
+        response.started_at = started_at;
+        response.finished_at = finished_at;
+        Ok(response)
+    }
+
+    /// Executes the request and returns only its body. Fails with
+    /// `CurlError::Http` if the status isn't a success (2xx).
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use curl_wrapper::Curl;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let body = Curl::new("https://example.com").send_text().await.unwrap();
+    ///     println!("Body: {}", body);
+    /// }
+    /// ```
+    pub async fn send_text(&self) -> Result<String, CurlError> {
+        let response = self.send().await?;
+        if !response.is_success() {
+            return Err(CurlError::Http {
+                status_code: response.status_code,
+            });
+        }
+        Ok(response.body)
+    }
+
+    /// Executes the request with the `HEAD` method, returning the status and
+    /// headers of the resource without its body.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use curl_wrapper::Curl;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let response = Curl::new("https://example.com").head().await.unwrap();
+    ///     println!("Headers: {:?}", response.headers);
+    /// }
+    /// ```
+    pub async fn head(&self) -> Result<CurlResponse, CurlError> {
+        self.clone().method(Method::HEAD).send().await
+    }
+
+    /// Executes the request like `send`, killing curl and failing with
+    /// `CurlError::Cancelled` if `token` is cancelled first.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use curl_wrapper::Curl;
+    /// use tokio_util::sync::CancellationToken;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let token = CancellationToken::new();
+    ///     let curl = Curl::new("https://example.com");
+    ///     let request = tokio::spawn({
+    ///         let token = token.clone();
+    ///         async move { curl.send_cancellable(token).await }
+    ///     });
+    ///     token.cancel();
+    ///     println!("Output: {:?}", request.await.unwrap());
+    /// }
+    /// ```
+    pub async fn send_cancellable(
+        &self,
+        token: CancellationToken,
+    ) -> Result<CurlResponse, CurlError> {
+        tokio::select! {
+            // Dropping the request kills curl.
+            _ = token.cancelled() => Err(CurlError::Cancelled),
+            response = self.send() => response,
+        }
+    }
+
+    /// Fails if the URL is empty, relative or otherwise malformed.
+    fn check_url(&self) -> Result<(), CurlError> {
+        let url = self.resolved_url();
+        match url::Url::parse(&url) {
+            Ok(_) => Ok(()),
+            Err(err) => Err(CurlError::InvalidInput(format!(
+                "invalid URL {:?}: {}",
+                url, err
+            ))),
+        }
+    }
+
+    /// Checks the request and builds the arguments passed to curl, pinning
+    /// the host to the address checked by `block_private_ips`. The cookie
+    /// file written for the cookie store must outlive curl.
+    async fn prepare(
+        &self,
+        streaming: bool,
+    ) -> Result<(Vec<String>, Option<NamedTempFile>), CurlError> {
+        self.check_url()?;
+        let mut args = self.curl_args(streaming)?;
+        if let Some(pin) = self.check_target().await? {
+            args.splice(0..0, ["--resolve".to_string(), pin]);
+        }
+        let jar = match &self.cookie_store {
+            Some(store) => {
+                let jar = NamedTempFile::new()?;
+                std::fs::write(jar.path(), store.lock().unwrap().to_netscape())?;
+                args.push("-b".to_string());
+                args.push(jar.path().to_string_lossy().to_string());
+                Some(jar)
+            }
+            None => None,
+        };
+        Ok((args, jar))
+    }
+
+    /// Checks that the target isn't a private address with
+    /// `block_private_ips`, returning the `--resolve` entry pinning the host
+    /// to the checked address if it was resolved.
+    async fn check_target(&self) -> Result<Option<String>, CurlError> {
+        if !self.block_private_ips || self.unix_socket.is_some() {
+            return Ok(None);
+        }
+        let url = url::Url::parse(&self.resolved_url())
+            .map_err(|err| CurlError::InvalidInput(format!("invalid URL: {}", err)))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+        let host = match url.host() {
+            Some(url::Host::Ipv4(ip)) => {
+                return check_public(&ip.to_string(), ip.into()).map(|_| None)
+            }
+            Some(url::Host::Ipv6(ip)) => {
+                return check_public(&ip.to_string(), ip.into()).map(|_| None)
+            }
+            Some(url::Host::Domain(host)) => host.to_string(),
+            None => return Err(CurlError::InvalidInput("URL has no host".to_string())),
+        };
+        let pinned = self.resolve.iter().find_map(|entry| {
+            let mut parts = entry.splitn(3, ':');
+            let (entry_host, entry_port) = (parts.next()?, parts.next()?);
+            let matches = entry_host.eq_ignore_ascii_case(&host)
+                && (entry_port == "*" || entry_port == port.to_string());
+            matches.then(|| parts.next().unwrap_or_default())
+        });
+        let addrs = match pinned {
+            Some(pinned) => pinned
+                .split(',')
+                .map(|addr| {
+                    let addr = addr.trim().trim_start_matches('[').trim_end_matches(']');
+                    addr.parse().map_err(|_| {
+                        CurlError::InvalidInput(format!("invalid resolve address {:?}", addr))
+                    })
+                })
+                .collect::<Result<Vec<IpAddr>, _>>()?,
+            None => tokio::net::lookup_host((host.as_str(), port))
+                .await?
+                .map(|addr| addr.ip())
+                .collect(),
+        };
+        for &addr in &addrs {
+            check_public(&host, addr)?;
+        }
+        match (pinned, addrs.first()) {
+            (None, Some(IpAddr::V4(ip))) => Ok(Some(format!("{}:{}:{}", host, port, ip))),
+            (None, Some(IpAddr::V6(ip))) => Ok(Some(format!("{}:{}:[{}]", host, port, ip))),
+            _ => Ok(None),
+        }
+    }
+
+    /// Runs curl with `args`, hedging it if enabled.
+    async fn output(&self, args: &[String]) -> Result<(Output, Option<NamedTempFile>), CurlError> {
+        let replayable = !matches!(self.body, Some(Body::Chunks(_)));
+        match self.hedge.filter(|_| replayable) {
+            Some(after) => {
+                let first = self.run(args);
+                tokio::pin!(first);
+                tokio::select! {
+                    output = &mut first => output,
+                    _ = tokio::time::sleep(after) => tokio::select! {
+                        output = &mut first => output,
+                        output = self.run(args) => output,
+                    },
+                }
+            }
+            None => self.run(args).await,
+        }
+    }
+
+    /// Builds the response from the output of curl, failing on curl and HTTP
+    /// errors. `spill` holds the output beyond the buffer limit.
+    fn response(
+        &self,
+        mut stdout: Vec<u8>,
+        mut stderr: &[u8],
+        exit_code: Option<i32>,
+        spill: Option<NamedTempFile>,
+    ) -> Result<CurlResponse, CurlError> {
+        // With a buffer limit, the write-out variables are on stderr.
+        if let Some(i) = find_bytes(stderr, WRITE_OUT_MARKER.as_bytes()) {
+            stdout.extend_from_slice(&stderr[i..]);
+            stderr = &stderr[..i];
+        }
+        #[cfg(feature = "decode")]
+        let mut response = CurlResponse::parse(stdout, self.curl_decodes(), self.capture_wire);
+        #[cfg(not(feature = "decode"))]
+        let mut response = CurlResponse::parse(stdout, self.curl_decodes(), false);
+        response.exit_code = exit_code.unwrap_or(-1);
+        response.spill = spill;
+        match exit_code {
+            Some(0) => {}
+            Some(22) if self.fail_on_error => {
+                return Err(CurlError::Http {
+                    status_code: response.status_code,
+                });
+            }
+            exit_code => {
+                let mut err = curl_error(exit_code, stderr);
+                if let (CurlError::Curl { response: slot, .. }, true) =
+                    (&mut err, response.is_parsed())
+                {
+                    *slot = Some(Box::new(response));
+                }
+                return Err(err);
+            }
+        }
+        if self.strict_parse {
+            if let Some((offset, snippet)) = response.malformed.take() {
+                return Err(CurlError::ParseError { offset, snippet });
+            }
+        }
+        match self.invalid_utf8 {
+            InvalidUtf8Strategy::Lossy => {}
+            InvalidUtf8Strategy::Error => {
+                if let Err(err) = std::str::from_utf8(&response.body_bytes) {
+                    return Err(CurlError::InvalidResponse(format!(
+                        "body is not valid UTF-8: {}",
+                        err
+                    )));
+                }
+            }
+            InvalidUtf8Strategy::Strip => {
+                let valid: String = response
+                    .body_bytes
+                    .utf8_chunks()
+                    .map(|chunk| chunk.valid())
+                    .collect();
+                response.body = valid.trim().to_string();
+            }
+        }
+        if self.verbose {
+            response.trace = Some(String::from_utf8_lossy(stderr).to_string());
+        }
+        if let Some(store) = &self.cookie_store {
+            // Each response of a redirect chain sets cookies for its own URL.
+            let mut store = store.lock().unwrap();
+            let mut url = self.resolved_url();
+            for (status_code, headers) in &response.history {
+                store.store(&url, headers);
+                let location = headers.iter().find_map(|header| {
+                    let (name, value) = header.split_once(':')?;
+                    name.trim()
+                        .eq_ignore_ascii_case("Location")
+                        .then_some(value.trim())
+                });
+                if let (300..=399, Some(location)) = (status_code, location) {
+                    if let Ok(next) = url::Url::parse(&url).and_then(|url| url.join(location)) {
+                        url = next.to_string();
+                    }
+                }
+            }
+        }
+        Ok(response)
+    }
+
+    /// Executes the request and streams the elements of the JSON array it
+    /// returns, without loading the whole array into memory.
+    ///
+    /// HTTP error statuses always fail the stream with `CurlError::Http`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use curl_wrapper::Curl;
+    /// use futures::StreamExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let curl = Curl::new("https://example.com/items.json");
+    ///     let mut items = Box::pin(curl.send_json_array_stream::<serde_json::Value>());
+    ///     while let Some(item) = items.next().await {
+    ///         println!("Item: {:?}", item.unwrap());
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn send_json_array_stream<T: serde::de::DeserializeOwned>(
+        &self,
+    ) -> impl futures::Stream<Item = Result<T, CurlError>> {
+        futures::StreamExt::flat_map(self.spawn_streaming(), json_stream::array_stream)
+    }
+
+    /// Executes the request and streams the lines of the body as they are
+    /// received, without their line endings, e.g. for NDJSON or server-sent
+    /// events. The status and headers aren't available in this mode.
+    ///
+    /// HTTP error statuses always fail the stream with `CurlError::Http`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use curl_wrapper::Curl;
+    /// use futures::StreamExt;
+    ///
+    /// #[tokio::main]
+    /// async fn main() {
+    ///     let curl = Curl::new("https://example.com/events");
+    ///     let mut lines = Box::pin(curl.send_stream());
+    ///     while let Some(line) = lines.next().await {
+    ///         println!("Line: {}", line.unwrap());
+    ///     }
+    /// }
+    /// ```
+    pub fn send_stream(&self) -> impl futures::Stream<Item = Result<String, CurlError>> {
+        futures::StreamExt::flat_map(self.spawn_streaming(), line_stream::line_stream)
+    }
+
+    /// Spawns curl in streaming mode once the returned stream is polled,
+    /// along with the cookie file it reads.
+    fn spawn_streaming(
+        &self,
+    ) -> futures::stream::Once<
+        impl Future<Output = Result<(Child, Option<NamedTempFile>), CurlError>>,
+    > {
+        let curl = self.clone();
+        futures::stream::once(async move {
+            let (args, jar) = curl.prepare(true).await?;
+            Ok((curl.spawn(&args)?, jar))
+        })
+    }
+
+    /// Spawns curl with `args` and piped output, killing the process if the
+    /// child is dropped. A body set with `body_bytes` is fed through stdin.
+    fn spawn(&self, args: &[String]) -> Result<Child, CurlError> {
+        let chunks = match &self.body {
+            Some(Body::Chunks(ChunkSource(chunks))) => match chunks.lock().unwrap().take() {
+                Some(chunks) => Some(chunks),
+                None => {
+                    return Err(CurlError::InvalidInput(
+                        "the body iterator was already consumed".to_string(),
+                    ))
+                }
+            },
+            _ => None,
+        };
+        let stdin = match &self.body {
+            Some(Body::Bytes(_) | Body::Chunks(_)) => Stdio::piped(),
+            _ => Stdio::null(),
+        };
+        let mut command = Command::new(&self.curl_path);
+        command.envs(self.env.iter().map(|(key, value)| (key, value)));
+        if self.clear_proxy_env {
+            for key in PROXY_ENV {
+                command.env_remove(key);
+                command.env_remove(key.to_ascii_uppercase());
+            }
+        }
+        let mut child = command
+            .args(args)
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // curl reports an incomplete body itself, so errors are ignored.
+            if let Some(chunks) = chunks {
+                tokio::spawn(async move {
+                    for chunk in chunks {
+                        if stdin.write_all(chunk.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            } else if let Some(Body::Bytes(body)) = &self.body {
+                let body = body.clone();
+                tokio::spawn(async move {
+                    let _ = stdin.write_all(&body).await;
+                });
+            }
+        }
+        Ok(child)
+    }
+
+    /// Runs curl with `args`, killing the process if the future is dropped or
+    /// stdout stays idle for longer than the idle timeout. Output beyond the
+    /// buffer limit is written to a temporary file.
+    async fn run(&self, args: &[String]) -> Result<(Output, Option<NamedTempFile>), CurlError> {
+        let mut child = self.spawn(args)?;
+        let mut stdout_pipe = child.stdout.take().expect("stdout is piped");
+        let mut stderr_pipe = child.stderr.take().expect("stderr is piped");
+        let stderr = tokio::spawn(async move {
+            let mut stderr = Vec::new();
+            stderr_pipe.read_to_end(&mut stderr).await.map(|_| stderr)
+        });
+        let mut stdout = Vec::new();
+        let mut head_end = None;
+        let mut spill = None;
+        let mut total = 0;
+        let mut written = 0;
+        let mut buf = [0; 8192];
+        loop {
+            let read = match self.idle_timeout {
+                Some(idle) => match tokio::time::timeout(idle, stdout_pipe.read(&mut buf)).await {
+                    Ok(read) => read?,
+                    Err(_) => {
+                        // Only the headers reach stdout when the body goes to
+                        // a file, so the file growing counts as progress.
+                        if let Some(path) = &self.output_file {
+                            let len = tokio::fs::metadata(path).await.map_or(0, |m| m.len());
+                            if len > written {
+                                written = len;
+                                continue;
+                            }
+                        }
+                        child.kill().await?;
+                        return Err(CurlError::IdleTimeout(idle));
+                    }
+                },
+                None => stdout_pipe.read(&mut buf).await?,
+            };
+            if read == 0 {
+                break;
+            }
+            total += read;
+            if let Some(max) = self.max_response_bytes.filter(|&max| total > max) {
+                child.kill().await?;
+                return Err(CurlError::ResponseTooLarge(max));
+            }
+            stdout.extend_from_slice(&buf[..read]);
+            // Only the body counts towards the buffer limit, so nothing is
+            // spilled until the headers have ended.
+            let Some(limit) = self.buffer_limit else {
+                continue;
+            };
+            if head_end.is_none() {
+                head_end = head_len(&stdout);
+            }
+            let Some(end) = head_end.filter(|&end| stdout.len() > end.saturating_add(limit)) else {
+                continue;
+            };
+            let rest = stdout.split_off(end + limit);
+            if spill.is_none() {
+                let file = NamedTempFile::new()?;
+                let writer = tokio::fs::File::from_std(file.reopen()?);
+                spill = Some((file, writer));
+            }
+            let (_, writer) = spill.as_mut().expect("spill file was created");
+            writer.write_all(&rest).await?;
+        }
+        let spill = match spill {
+            Some((file, mut writer)) => {
+                writer.flush().await?;
+                Some(file)
+            }
+            None => None,
+        };
+        let status = child.wait().await?;
+        let stderr = stderr.await.map_err(io::Error::other)??;
+        Ok((
+            Output {
+                status,
+                stdout,
+                stderr,
+            },
+            spill,
+        ))
+    }
+
+    /// Returns the arguments `send` would pass to curl, without running it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let args = Curl::new("https://example.com")
+    ///     .redirects(true)
+    ///     .build_args()
+    ///     .unwrap();
+    /// assert!(args.contains(&"-L".to_string()));
+    /// ```
+    pub fn build_args(&self) -> Result<Vec<String>, CurlError> {
+        self.curl_args(false)
+    }
+
+    /// Builds the arguments passed to curl. When `streaming`, the body is
+    /// written to stdout alone and HTTP errors fail the transfer, with the
+    /// status code written out to stderr.
+    fn curl_args(&self, streaming: bool) -> Result<Vec<String>, CurlError> {
+        let proxies = [&self.proxy, &self.socks5, &self.socks5_hostname];
+        if proxies.iter().filter(|p| p.is_some()).count() > 1 {
+            return Err(CurlError::InvalidInput(
+                "only one of set_proxy, socks5 and socks5h can be used".to_string(),
+            ));
+        }
+
+        // A proxy from the environment gives way to any proxy set explicitly.
+        let proxy = self.proxy.as_ref().or_else(|| {
+            self.env_proxy
+                .as_ref()
+                .filter(|_| self.socks5.is_none() && self.socks5_hostname.is_none())
+        });
+        let has_proxy = proxy.is_some() || proxies.iter().any(|p| p.is_some());
+        if self.block_private_ips && (self.redirects || has_proxy) {
+            return Err(CurlError::InvalidInput(
+                "block_private_ips can't be combined with redirects or a proxy".to_string(),
+            ));
+        }
+
+        if self.safe_mode {
+            self.check_smuggling()?;
+        }
+
+        if self.ipv4 && self.ipv6 {
+            return Err(CurlError::InvalidInput(
+                "only one of ipv4 and ipv6 can be used".to_string(),
+            ));
+        }
+
+        let url = self.resolved_url();
+        let is_http = url
+            .get(..7)
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case("http://"));
+        if is_http && !self.allow_http {
+            return Err(CurlError::InvalidInput(format!(
+                "plaintext HTTP isn't allowed: {}",
+                url
+            )));
+        }
+
+        for header in self.all_headers() {
+            // `Name: value`, or `Name;` for an empty value.
+            let name = header_name(header);
+            if name.len() == header.len() || name.is_empty() || name.starts_with('-') {
+                return Err(CurlError::InvalidInput(format!(
+                    "malformed header {:?}",
+                    header
+                )));
+            }
+        }
+
+        let mut args = Vec::new();
+        if self.silent {
+            args.push("--silent".to_string());
+        }
+        args.push("--show-error".to_string());
+        // The headers are dumped to stdout on their own when the body goes
+        // to a file, so that they are parsed the same way.
+        match &self.output_file {
+            Some(path) if !streaming => {
+                args.push("-D".to_string());
+                args.push("-".to_string());
+                args.push("-o".to_string());
+                args.push(path.clone());
+                if self.create_dirs {
+                    args.push("--create-dirs".to_string());
+                }
+                // `idle_timeout` watches the file grow, so it's written
+                // unbuffered.
+                if self.idle_timeout.is_some() {
+                    args.push("--no-buffer".to_string());
+                }
+            }
+            None if !streaming => args.push("--include".to_string()),
+            _ => {}
+        }
+        // The response of a proxy to CONNECT, set here or in the environment,
+        // would look like the response itself.
+        if !streaming {
+            args.push("--suppress-connect-headers".to_string());
+        }
+
+        if let Some(interface) = &self.interface {
+            args.push("--interface".to_string());
+            args.push(interface.clone());
+        }
+
+        if let Some(range) = &self.local_port {
+            let port = |port: &str| port.parse::<u16>().ok().filter(|&port| port > 0);
+            let valid = match range.split_once('-') {
+                Some((start, end)) => port(start).zip(port(end)).is_some_and(|(s, e)| s <= e),
+                None => port(range).is_some(),
+            };
+            if !valid {
+                return Err(CurlError::InvalidInput(format!(
+                    "invalid local port range {:?}",
+                    range
+                )));
+            }
+            args.push("--local-port".to_string());
+            args.push(range.clone());
+        }
+
+        match self.keep_alive {
+            Some(true) => args.push("--keepalive".to_string()),
+            Some(false) => args.push("--no-keepalive".to_string()),
+            None => {}
+        }
+
+        if let Some(time) = self.keep_alive_time {
+            args.push("--keepalive-time".to_string());
+            // curl requires at least one second.
+            args.push(time.as_secs().max(1).to_string());
+        }
+
+        if let (Some(min), Some(max)) = (self.tls_min, self.tls_max) {
+            if min > max {
+                return Err(CurlError::InvalidInput(format!(
+                    "the minimum TLS version {} is higher than the maximum {}",
+                    min.as_str(),
+                    max.as_str()
+                )));
+            }
+        }
+
+        if let Some(min) = self.tls_min {
+            args.push(format!("--tlsv{}", min.as_str()));
+        }
+
+        if let Some(max) = self.tls_max {
+            args.push("--tls-max".to_string());
+            args.push(max.as_str().to_string());
+        }
+
+        if let Some(path) = self.cacert.as_ref().or(self.env_cacert.as_ref()) {
+            args.push("--cacert".to_string());
+            args.push(path.clone());
+        }
+
+        if let Some(path) = &self.tls_session_cache {
+            args.push("--ssl-sessions".to_string());
+            args.push(path.clone());
+        }
+
+        if self.tcp_fastopen {
+            args.push("--tcp-fastopen".to_string());
+        }
+
+        match self.tcp_nodelay {
+            Some(true) => args.push("--tcp-nodelay".to_string()),
+            Some(false) => args.push("--no-tcp-nodelay".to_string()),
+            None => {}
+        }
+
+        if let Some(unix_socket) = &self.unix_socket {
+            args.push("--unix-socket".to_string());
+            args.push(unix_socket.clone());
+        }
+
+        if self.ipv4 {
+            args.push("-4".to_string());
+        }
+
+        if self.ipv6 {
+            args.push("-6".to_string());
+        }
+
+        for entry in &self.resolve {
+            args.push("--resolve".to_string());
+            args.push(entry.clone());
+        }
+
+        if self.redirects {
+            args.push("-L".to_string());
+        }
+
+        if !self.allow_http {
+            args.push("--proto-redir".to_string());
+            args.push("=https".to_string());
+        }
+
+        if self.fail_on_error || streaming {
+            args.push("--fail".to_string());
+        }
+
+        if let Some(rate) = &self.limit_rate {
+            let re = Regex::new(r"^\d+[kKmMgGtT]?$").unwrap();
+            if !re.is_match(rate) {
+                return Err(CurlError::InvalidInput(format!(
+                    "invalid rate limit {:?}",
+                    rate
+                )));
+            }
+            args.push("--limit-rate".to_string());
+            args.push(rate.clone());
+        }
+
+        if let Some(max_size) = self.max_size {
+            args.push("--max-filesize".to_string());
+            args.push(max_size.to_string());
+        }
+
+        if let Some(range) = &self.range {
+            args.push("-r".to_string());
+            args.push(range.clone());
+        }
+
+        if let Some(remaining) = self.deadline.as_ref().and_then(|d| d.0.remaining()) {
+            // curl treats a max time of 0 as no limit at all.
+            if remaining < Duration::from_millis(1) {
+                return Err(CurlError::InvalidInput(
+                    "the deadline has already passed".to_string(),
+                ));
+            }
+            args.push("--max-time".to_string());
+            args.push(format!("{:.3}", remaining.as_secs_f64()));
+        }
+
+        if self.verbose {
+            args.push("-v".to_string());
+        }
+
+        // With `-X HEAD`, curl would wait for a body that never comes.
+        if self.method == Some(Method::HEAD) {
+            args.push("--head".to_string());
+        } else {
+            let method = self.method.as_ref().map_or("GET", Method::as_str);
+            args.push("-X".to_string());
+            args.push(method.to_string());
+        }
+
+        if let Some(proxy) = proxy {
+            args.push("--proxy".to_string());
+            args.push(proxy.clone());
+        }
+
+        if let Some(hosts) = self.no_proxy.as_ref().or(self.env_no_proxy.as_ref()) {
+            args.push("--noproxy".to_string());
+            args.push(hosts.clone());
+        }
+
+        if let Some(socks5) = &self.socks5 {
+            args.push("--socks5".to_string());
+            args.push(socks5.clone());
+        }
+
+        if let Some(socks5_hostname) = &self.socks5_hostname {
+            args.push("--socks5-hostname".to_string());
+            args.push(socks5_hostname.clone());
+        }
+
+        if let Some((method, user, pass)) = &self.auth {
+            let flag = match method {
+                AuthMethod::Basic => "--basic",
+                AuthMethod::Digest => "--digest",
+                AuthMethod::Ntlm => "--ntlm",
+                AuthMethod::Bearer => "--oauth2-bearer",
+            };
+            args.push(flag.to_string());
+            if *method == AuthMethod::Bearer {
+                args.push(pass.clone());
+            } else {
+                args.push("--user".to_string());
+                args.push(format!("{}:{}", user, pass));
+            }
+        }
+
+        args.extend(self.raw_args.iter().cloned());
+
+        // `--url` keeps a URL starting with `-` from being read as a flag.
+        args.push("--url".to_string());
+        args.push(self.resolved_url());
+
+        if let Some(user_agent) = &self.user_agent {
+            args.push("-A".to_string());
+            args.push(user_agent.clone());
+        }
+
+        for i in self.all_headers() {
+            let is_user_agent = i
+                .split_once(':')
+                .is_some_and(|(name, _)| name.trim().eq_ignore_ascii_case("User-Agent"));
+            if is_user_agent && self.user_agent.is_some() {
+                continue;
+            }
+            args.push("-H".to_string());
+            if self.canonicalize_headers {
+                args.push(canonical_header(i));
+            } else {
+                args.push(i.clone());
+            }
+        }
+
+        if !self.cookies.is_empty() {
+            args.push("-b".to_string());
+            args.push(self.cookies.join("; "));
+        }
+
+        if let Some(cookie_file) = &self.cookie_file {
+            args.push("-b".to_string());
+            args.push(cookie_file.clone());
+        }
+
+        if let Some(cookie_jar) = &self.cookie_jar {
+            args.push("-c".to_string());
+            args.push(cookie_jar.clone());
+        }
+
+        match &self.body {
+            Some(Body::Text(body)) => {
+                args.push("-d".to_string());
+                args.push(body.clone());
+            }
+            Some(Body::Bytes(_)) => {
+                args.push("--data-binary".to_string());
+                args.push("@-".to_string());
+            }
+            Some(Body::File(path)) => {
+                args.push("--data-binary".to_string());
+                args.push(format!("@{}", path));
+            }
+            Some(Body::Chunks(_)) => {
+                // Uploading stdin streams it; `--data-binary @-` would read
+                // all of it first.
+                args.push("-T".to_string());
+                args.push("-".to_string());
+            }
+            None => {}
+        }
+
+        // Don't wait for a `100 Continue` the server may never send, which a
+        // streamed body can't afford.
+        if !self.expect_continue || matches!(self.body, Some(Body::Chunks(_))) {
+            args.push("-H".to_string());
+            args.push("Expect:".to_string());
+        }
+
+        let decode_in_rust = self.compressed && !self.curl_decodes();
+        if self.compressed {
+            // curl keeps decoding with `--compressed` when the header is set.
+            if decode_in_rust || !self.compression_algorithms.is_empty() {
+                let algorithms = if self.compression_algorithms.is_empty() {
+                    "gzip, deflate, br".to_string()
+                } else {
+                    self.compression_algorithms.join(", ")
+                };
+                args.push("-H".to_string());
+                args.push(format!("Accept-Encoding: {}", algorithms));
+            }
+            if self.curl_decodes() {
+                args.push("--compressed".to_string());
+            }
+        }
+
+        if streaming {
+            args.push("--write-out".to_string());
+            args.push(format!(
+                "%{{stderr}}{}http_code=%{{http_code}}\n",
+                WRITE_OUT_MARKER
+            ));
+            return Ok(args);
+        }
+
+        let mut write_out = vec!["size_upload", "size_download"];
+        if self.redirects {
+            write_out.push("url_effective");
+        }
+        if self.timing {
+            write_out.extend([
+                "time_namelookup",
+                "time_connect",
+                "time_appconnect",
+                "time_total",
+            ]);
+        }
+        let vars: Vec<String> = write_out
+            .iter()
+            .map(|var| format!("{}=%{{{}}}\n", var, var))
+            .collect();
+        args.push("--write-out".to_string());
+        // Past the buffer limit, stdout ends up on disk, and the write-out
+        // mustn't count towards the response size limit.
+        let stream = if self.buffer_limit.is_some() || self.max_response_bytes.is_some() {
+            "%{stderr}"
+        } else {
+            ""
+        };
+        args.push(format!("{}{}{}", stream, WRITE_OUT_MARKER, vars.concat()));
+
+        Ok(args)
+    }
+
+    /// Whether curl decodes the body itself, with `--compressed`, instead of
+    /// leaving it to `capture_wire`.
+    fn curl_decodes(&self) -> bool {
+        #[cfg(feature = "decode")]
+        let capture_wire = self.capture_wire;
+        #[cfg(not(feature = "decode"))]
+        let capture_wire = false;
+        self.compressed && !capture_wire
+    }
+
+    /// Rejects input that could enable HTTP request smuggling.
+    fn check_smuggling(&self) -> Result<(), CurlError> {
+        let is_get = matches!(self.method, None | Some(Method::GET | Method::HEAD));
+        if is_get && self.body.is_some() {
+            return Err(CurlError::InvalidInput(
+                "GET and HEAD requests can't have a body".to_string(),
+            ));
+        }
+        let unsafe_char = |c: char| matches!(c, '\r' | '\n' | '\0');
+        if self.resolved_url().contains(unsafe_char) {
+            return Err(CurlError::InvalidInput(
+                "URL contains CR, LF or NUL".to_string(),
+            ));
+        }
+        for value in self.all_headers().chain(&self.cookies) {
+            if value.contains(unsafe_char) {
+                return Err(CurlError::InvalidInput(format!(
+                    "header or cookie {:?} contains CR, LF or NUL",
+                    value
+                )));
+            }
+        }
+
+        let values = |name: &str| -> Vec<String> {
+            self.all_headers()
+                .filter_map(|header| header.split_once(':'))
+                .filter(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.trim().to_string())
+                .collect()
+        };
+        let content_lengths = values("Content-Length");
+        if !values("Transfer-Encoding").is_empty() && !content_lengths.is_empty() {
+            return Err(CurlError::InvalidInput(
+                "Transfer-Encoding can't be combined with Content-Length".to_string(),
+            ));
+        }
+        if content_lengths
+            .iter()
+            .any(|length| length != &content_lengths[0])
+        {
+            return Err(CurlError::InvalidInput(
+                "conflicting Content-Length headers".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the default headers not replaced by a header of the request,
+    /// followed by the headers of the request.
+    fn all_headers(&self) -> impl Iterator<Item = &String> {
+        let defaults = self.default_headers.iter().filter(|default| {
+            let name = header_name(default).trim();
+            !self
+                .headers
+                .iter()
+                .any(|header| header_name(header).trim().eq_ignore_ascii_case(name))
+        });
+        defaults.chain(&self.headers)
+    }
+
+    /// Returns the command line `send` would run, quoted for a POSIX shell.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let command = Curl::new("https://example.com")
+    ///     .set_header("Accept: application/json")
+    ///     .to_command_string()
+    ///     .unwrap();
+    /// println!("{}", command);
+    /// ```
+    pub fn to_command_string(&self) -> Result<String, CurlError> {
+        let mut words = vec![shell_quote(&self.curl_path)];
+        for arg in self.build_args()? {
+            words.push(shell_quote(&arg));
+        }
+        Ok(words.join(" "))
+    }
+}
+
+/// Fails if `addr`, which `host` resolves to, is a private, shared
+/// (carrier-grade NAT), loopback, link-local or unspecified address.
+/// IPv4 addresses embedded in IPv6 ones are checked as well.
+fn check_public(host: &str, addr: IpAddr) -> Result<(), CurlError> {
+    let is_private = match addr {
+        IpAddr::V4(ip) => {
+            let shared = ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64;
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || shared
+        }
+        IpAddr::V6(ip) if ip.is_loopback() || ip.is_unspecified() => true,
+        IpAddr::V6(ip) => {
+            let segments = ip.segments();
+            let embedded = Ipv4Addr::from(u128::from(ip) as u32);
+            // IPv4-mapped and IPv4-compatible (`::a.b.c.d`) addresses, and
+            // the NAT64 well-known prefix `64:ff9b::/96`.
+            if ip.to_ipv4().is_some() || segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                return check_public(host, embedded.into());
+            }
+            let unique_local = segments[0] & 0xfe00 == 0xfc00;
+            let link_local = segments[0] & 0xffc0 == 0xfe80;
+            unique_local || link_local
+        }
+    };
+    if is_private {
+        return Err(CurlError::InvalidInput(format!(
+            "{} resolves to the private address {}",
+            host, addr
+        )));
+    }
+    Ok(())
+}
+
+/// Returns the name of `header`, which is either `Name: value` or `Name;`.
+fn header_name(header: &str) -> &str {
+    header.split([':', ';']).next().unwrap_or_default()
+}
+
+/// Rewrites the name of `header` to Title-Case, keeping its value as is.
+fn canonical_header(header: &str) -> String {
+    let canonical_name = |name: &str| -> String {
+        let words: Vec<String> = name
+            .split('-')
+            .map(|word| {
+                let mut chars = word.chars();
+                match chars.next() {
+                    Some(first) => {
+                        first.to_ascii_uppercase().to_string()
+                            + &chars.as_str().to_ascii_lowercase()
+                    }
+                    None => String::new(),
+                }
+            })
+            .collect();
+        words.join("-")
+    };
+    match header.split_once(':') {
+        Some((name, value)) => format!("{}:{}", canonical_name(name), value),
+        None => canonical_name(header),
+    }
+}
+
+/// Quotes `word` for a POSIX shell, leaving it untouched when it is safe as is.
+fn shell_quote(word: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "@%+=:,./-_".contains(c);
+    if !word.is_empty() && word.chars().all(safe) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+impl SecurityHeaders {
+    /// Returns the names of the recommended headers missing from the response.
+    pub fn missing(&self) -> Vec<&'static str> {
+        let mut missing = Vec::new();
+        if self.strict_transport_security.is_none() {
+            missing.push("Strict-Transport-Security");
+        }
+        if self.content_security_policy.is_none() {
+            missing.push("Content-Security-Policy");
+        }
+        if self.x_frame_options.is_none() {
+            missing.push("X-Frame-Options");
+        }
+        missing
+    }
+}
+
+impl CachePolicy {
+    /// Whether the response may be stored by a cache.
+    pub fn is_cacheable(&self) -> bool {
+        !self.no_store
+    }
+
+    /// Returns how long the response stays fresh, or `None` if it has no
+    /// explicit freshness lifetime. `max-age` takes precedence over
+    /// `Expires`, and a `no-cache` response is never fresh.
+    pub fn remaining_freshness(&self) -> Option<Duration> {
+        let lifetime = match (self.max_age, self.expires) {
+            (Some(max_age), _) => max_age,
+            (None, Some(expires)) => {
+                let date = self.date.unwrap_or_else(SystemTime::now);
+                expires.duration_since(date).unwrap_or_default()
+            }
+            (None, None) => return None,
+        };
+        if self.no_cache {
+            return Some(Duration::ZERO);
+        }
+        Some(lifetime.saturating_sub(self.age))
+    }
+}
+
+#[cfg(feature = "http")]
+impl From<CurlResponse> for http::Response<Vec<u8>> {
+    /// Converts the response into an `http::Response`. Headers with invalid
+    /// names or values are skipped, and a status code that isn't valid (e.g.
+    /// `0` when no status line was found) becomes `502 Bad Gateway`.
+    fn from(response: CurlResponse) -> Self {
+        let mut converted = http::Response::new(response.body_bytes);
+        *converted.status_mut() = http::StatusCode::from_u16(response.status_code)
+            .unwrap_or(http::StatusCode::BAD_GATEWAY);
+        let headers = converted.headers_mut();
+        for header in &response.headers {
+            let Some((name, value)) = header.split_once(':') else {
+                continue;
+            };
+            let name = http::HeaderName::from_bytes(name.trim().as_bytes());
+            let value = http::HeaderValue::from_str(value.trim());
+            if let (Ok(name), Ok(value)) = (name, value) {
+                headers.append(name, value);
+            }
+        }
+        converted
+    }
+}
+
+/// Builds the error for a curl run that exited with `exit_code`.
+fn curl_error(exit_code: Option<i32>, stderr: &[u8]) -> CurlError {
+    let stderr = String::from_utf8_lossy(stderr);
+    let message = stderr
+        .lines()
+        .rev()
+        .find(|line| line.starts_with("curl:"))
+        .unwrap_or(stderr.trim());
+    CurlError::Curl {
+        exit_code,
+        message: message.to_string(),
+        response: None,
+    }
+}
+
+/// Returns the position of the first occurrence of `needle` in `haystack`.
+fn find_bytes(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Returns the length of the header blocks at the start of curl's stdout,
+/// split the way `CurlResponse` parses them, or `None` until it's known.
+fn head_len(stdout: &[u8]) -> Option<usize> {
+    let mut end = 0;
+    loop {
+        let rest = &stdout[end..];
+        end += find_bytes(rest, b"\r\n\r\n")? + 4;
+        let code = rest.split(|&byte| byte == b' ').nth(1)?;
+        if !matches!(code.first(), Some(b'1' | b'3')) {
+            return Some(end);
+        }
+        // Informational and redirect responses may be followed by another.
+        let next = &stdout[end..];
+        if next.len() < 5 && b"HTTP/".starts_with(next) {
+            return None;
+        }
+        if !next.starts_with(b"HTTP/") {
+            return Some(end);
+        }
+    }
+}
+
+/// Splits curl's stdout into the response and the `--write-out` variables.
+fn split_write_out(stdout: &[u8]) -> (&[u8], HashMap<String, String>) {
+    let marker = WRITE_OUT_MARKER.as_bytes();
+    let position = stdout
+        .windows(marker.len())
+        .rposition(|window| window == marker);
+    match position {
+        Some(position) => {
+            let vars = String::from_utf8_lossy(&stdout[position + marker.len()..])
+                .lines()
+                .filter_map(|line| line.split_once('='))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            (&stdout[..position], vars)
+        }
+        None => (stdout, HashMap::new()),
+    }
+}
+
+impl fmt::Debug for CurlResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "CurlResponse {{ status_code: {}, reason: {:?}, headers: {:?}, body: {:?}, timing: {:?}, trace: {:?} }}",
+            self.status_code, self.reason, self.headers, self.body, self.timing, self.trace
+        )
+    }
+}
+
+impl Timing {
+    /// Reads the timing variables from curl's `--write-out` output.
+    fn from_write_out(write_out: &HashMap<String, String>) -> Option<Self> {
+        let time = |var: &str| -> Option<Duration> {
+            let secs: f64 = write_out.get(var)?.parse().ok()?;
+            Duration::try_from_secs_f64(secs).ok()
+        };
+        Some(Timing {
+            namelookup: time("time_namelookup")?,
+            connect: time("time_connect")?,
+            appconnect: time("time_appconnect")?,
+            total: time("time_total")?,
+        })
+    }
+}
+
+impl CurlResponse {
+    /// Create a new CurlResponse from an Output.
+    ///
+    /// # Arguments
+    /// * `output` - The output of the curl command.
+    ///
+    /// # Returns
+    /// A new CurlResponse.
+    ///
+    /// # Example
+    /// ```
+    /// use curl_wrapper::CurlResponse;
+    ///
+    /// let output = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<html><body><h1>Hello, World!</h1></body></html>";
+    /// let response = CurlResponse::new(output.to_vec());
+    /// println!("Status Code: {}", response.status_code);
+    /// println!("Headers: {:?}", response.headers);
+    /// println!("Body: {:?}", response.body);
+    /// ```
+    pub fn new(stdout: Vec<u8>) -> Self {
+        Self::parse(stdout, false, false)
+    }
+
+    /// Parses the output of curl, whose body curl already decoded if
+    /// `decoded`, keeping the body as received in `wire_bytes` if
+    /// `capture_wire` is set.
+    #[cfg_attr(not(feature = "decode"), allow(unused_variables))]
+    fn parse(stdout: Vec<u8>, decoded: bool, capture_wire: bool) -> Self {
+        let now = SystemTime::now();
+        let (stdout, write_out) = split_write_out(&stdout);
+        let re = Regex::new(r"HTTP/.*?\s(\d{3})(?: ([^\r\n]*))?").unwrap();
+        let mut status_code = 0;
+        let mut reason = String::new();
+        let mut headers = Vec::new();
+        let mut body_bytes = Vec::new();
+        let mut rest = stdout;
+        let mut malformed = None;
+        let mut history = Vec::new();
+        loop {
+            let offset = stdout.len() - rest.len();
+            let Some(end) = find_bytes(rest, b"\r\n\r\n") else {
+                malformed = Some(offset);
+                break;
+            };
+            let block = String::from_utf8_lossy(&rest[..end]);
+            // `\d` also matches non-ASCII digits, which don't parse as a u16.
+            let Some((capture, code, status)) = re.captures(&block).and_then(|capture| {
+                let code = capture.get(1)?.as_str();
+                let status = code.parse().ok()?;
+                Some((capture, code, status))
+            }) else {
+                malformed = Some(offset);
+                break;
+            };
+            status_code = status;
+            reason = capture
+                .get(2)
+                .map_or("", |reason| reason.as_str().trim())
+                .to_string();
+            headers = block
+                .lines()
+                .skip(1)
+                .take_while(|line| !line.is_empty())
+                .map(|line| line.trim().to_string())
+                // HTTP/2 pseudo-headers such as `:status` aren't headers.
+                .filter(|line| !line.starts_with(':'))
+                .collect();
+            history.push((status_code, headers.clone()));
+            rest = &rest[end + 4..];
+            // Informational and redirect responses are followed by the next
+            // response in the dump.
+            if (code.starts_with('1') || code.starts_with('3')) && rest.starts_with(b"HTTP/") {
+                continue;
+            }
+            body_bytes = rest.to_vec();
+            break;
+        }
+        let mut response = CurlResponse {
+            status_code,
+            reason,
+            headers,
+            history,
+            body: String::new(),
+            body_bytes,
+            timing: Timing::from_write_out(&write_out),
+            effective_url: write_out.get("url_effective").cloned(),
+            exit_code: 0,
+            trace: None,
+            started_at: now,
+            finished_at: now,
+            bytes_sent: write_out
+                .get("size_upload")
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(0),
+            bytes_received: write_out
+                .get("size_download")
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(0),
+            #[cfg(feature = "decode")]
+            wire_bytes: None,
+            spill: None,
+            malformed: malformed.map(|offset| {
+                let end = stdout.len().min(offset + 32);
+                (
+                    offset,
+                    String::from_utf8_lossy(&stdout[offset..end]).to_string(),
+                )
+            }),
+            decoded,
+        };
+        #[cfg(feature = "decode")]
+        {
+            if capture_wire {
+                response.wire_bytes = Some(response.body_bytes.clone());
+            }
+            // A compressed payload, e.g. a `.gz` file, must not be decoded
+            // a second time.
+            if !decoded {
+                response.decode_body();
+            }
+        }
+        response.body = String::from_utf8_lossy(&response.body_bytes)
+            .trim()
+            .to_string();
+        response
+    }
+
+    /// Returns the `Content-Encoding` of the response if it is one of
+    /// `gzip`, `deflate` or `br`.
+    fn content_encoding(&self) -> Option<&'static str> {
+        let encoding = self.header("Content-Encoding")?.to_ascii_lowercase();
+        match encoding.as_str() {
+            "gzip" | "x-gzip" => Some("gzip"),
+            "deflate" => Some("deflate"),
+            "br" => Some("br"),
+            _ => None,
+        }
+    }
+
+    /// Returns whether the body is still compressed, i.e. the response has a
+    /// `Content-Encoding` and the body wasn't decoded by curl (with
+    /// `compressed(true)`) or by the `decode` feature. `body` is then a lossy
+    /// rendering of binary data; use `body_bytes` instead.
+    ///
+    /// # Example
+    /// ```
+    /// use curl_wrapper::CurlResponse;
+    ///
+    /// let output = b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\nHello, World!";
+    /// let response = CurlResponse::new(output.to_vec());
+    /// assert!(!response.is_compressed());
+    /// ```
+    pub fn is_compressed(&self) -> bool {
+        self.content_encoding().is_some() && !self.decoded
+    }
+
+    /// Decodes a body left compressed by curl according to its
+    /// `Content-Encoding`, leaving it untouched if decoding fails.
+    #[cfg(feature = "decode")]
+    fn decode_body(&mut self) {
+        use std::io::Read;
+
+        let body = &self.body_bytes[..];
+        let mut decoded = Vec::new();
+        let result = match self.content_encoding() {
+            Some("gzip") => flate2::read::MultiGzDecoder::new(body).read_to_end(&mut decoded),
+            Some("deflate") => flate2::read::ZlibDecoder::new(body).read_to_end(&mut decoded),
+            Some("br") => brotli::Decompressor::new(body, 4096).read_to_end(&mut decoded),
+            _ => return,
+        };
+        if result.is_ok() {
+            self.body_bytes = decoded;
+            self.decoded = true;
+        }
+    }
+
+    /// Returns the value of the first header named `name`, ignoring case.
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find_map(|header| {
+            let (key, value) = header.split_once(':')?;
+            key.trim()
+                .eq_ignore_ascii_case(name)
+                .then_some(value.trim())
+        })
+    }
+
+    /// Returns the values of the headers named `name`, ignoring case, joined
+    /// with `, ` (RFC 7230 section 3.2.2), or `None` if there is none.
+    ///
+    /// `Set-Cookie` values can't be joined, so `None` is always returned for
+    /// it; read them with `headers_all`.
+    ///
+    /// # Example
+    /// ```
+    /// use curl_wrapper::CurlResponse;
+    ///
+    /// let output = b"HTTP/1.1 200 OK\r\nVary: Accept\r\nVary: Origin\r\n\r\n";
+    /// let response = CurlResponse::new(output.to_vec());
+    /// assert_eq!(response.joined_header("vary").as_deref(), Some("Accept, Origin"));
+    /// ```
+    pub fn joined_header(&self, name: &str) -> Option<String> {
+        if name.eq_ignore_ascii_case("Set-Cookie") {
+            return None;
+        }
+        let values = self.headers_all(name);
+        (!values.is_empty()).then(|| values.join(", "))
+    }
+
+    /// Returns the values of all the headers named `name`, ignoring case, in
+    /// order.
+    ///
+    /// # Example
+    /// ```
+    /// use curl_wrapper::CurlResponse;
+    ///
+    /// let output = b"HTTP/1.1 200 OK\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\n\r\n";
+    /// let response = CurlResponse::new(output.to_vec());
+    /// assert_eq!(response.headers_all("set-cookie"), ["a=1", "b=2"]);
+    /// ```
+    pub fn headers_all(&self, name: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter_map(|header| {
+                let (key, value) = header.split_once(':')?;
+                key.trim()
+                    .eq_ignore_ascii_case(name)
+                    .then_some(value.trim())
+            })
+            .collect()
+    }
+
+    /// Returns the protocol negotiated with ALPN during the TLS handshake,
+    /// e.g. `h2` or `http/1.1`, read from the trace of a request sent with
+    /// `verbose(true)`. Returns `None` without a trace or TLS.
+    ///
+    /// # Example
+    /// ```
+    /// use curl_wrapper::CurlResponse;
+    ///
+    /// let mut response = CurlResponse::new(b"HTTP/2 200\r\n\r\n".to_vec());
+    /// response.trace = Some("* ALPN: server accepted h2\n".to_string());
+    /// assert_eq!(response.alpn_protocol().as_deref(), Some("h2"));
+    /// ```
+    pub fn alpn_protocol(&self) -> Option<String> {
+        self.trace.as_deref()?.lines().find_map(|line| {
+            // `* ALPN: server accepted h2`, or with older curl versions
+            // `* ALPN, server accepted to use h2`.
+            let rest = line
+                .strip_prefix("* ALPN: server accepted ")
+                .or_else(|| line.strip_prefix("* ALPN, server accepted to use "))?;
+            Some(rest.trim().to_string())
+        })
+    }
+
+    /// Whether a status line and the end of the headers were found in the
+    /// output. When they weren't, e.g. for a non-HTTP response,
+    /// `status_code` is 0; `strict_parse` turns this into an error.
+    ///
+    /// # Example
+    /// ```
+    /// use curl_wrapper::CurlResponse;
+    ///
+    /// let response = CurlResponse::new(b"SSH-2.0-OpenSSH_9.2\r\n".to_vec());
+    /// assert!(!response.is_parsed());
+    /// ```
+    pub fn is_parsed(&self) -> bool {
+        self.malformed.is_none()
+    }
+
+    /// Whether the status code is a success (2xx).
+    ///
+    /// # Example
+    /// ```
+    /// use curl_wrapper::CurlResponse;
+    ///
+    /// let response = CurlResponse::new(b"HTTP/1.1 204 No Content\r\n\r\n".to_vec());
+    /// assert!(response.is_success());
+    /// ```
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status_code)
+    }
+
+    /// Whether the status code is a redirection (3xx).
+    pub fn is_redirect(&self) -> bool {
+        (300..400).contains(&self.status_code)
+    }
+
+    /// Whether the status code is a client error (4xx).
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.status_code)
+    }
+
+    /// Whether the status code is a server error (5xx).
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.status_code)
+    }
+
+    /// Returns the parsed `Content-Range` header, e.g. from a `206 Partial
+    /// Content` response. Returns `None` if the header is missing, malformed
+    /// or has no range (`bytes */1024`).
+    ///
+    /// # Example
+    /// ```
+    /// use curl_wrapper::CurlResponse;
+    ///
+    /// let output = b"HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 0-99/1024\r\n\r\n";
+    /// let range = CurlResponse::new(output.to_vec()).content_range().unwrap();
+    /// assert_eq!((range.start, range.end, range.total), (0, 99, Some(1024)));
+    /// ```
+    pub fn content_range(&self) -> Option<ContentRange> {
+        let range = self.header("Content-Range")?.strip_prefix("bytes ")?;
+        let (range, total) = range.split_once('/')?;
+        let (start, end) = range.split_once('-')?;
+        let total = match total.trim() {
+            "*" => None,
+            total => Some(total.parse().ok()?),
+        };
+        Some(ContentRange {
+            start: start.trim().parse().ok()?,
+            end: end.trim().parse().ok()?,
+            total,
+        })
+    }
+
+    /// Returns the security-relevant headers of the response.
+    ///
+    /// # Example
+    /// ```
+    /// use curl_wrapper::CurlResponse;
+    ///
+    /// let output = b"HTTP/1.1 200 OK\r\nX-Frame-Options: DENY\r\n\r\n";
+    /// let response = CurlResponse::new(output.to_vec());
+    /// let security = response.security_headers();
+    /// assert_eq!(security.x_frame_options.as_deref(), Some("DENY"));
+    /// assert_eq!(security.missing(), ["Strict-Transport-Security", "Content-Security-Policy"]);
+    /// ```
+    pub fn security_headers(&self) -> SecurityHeaders {
+        let header = |name| self.header(name).map(str::to_string);
+        SecurityHeaders {
+            strict_transport_security: header("Strict-Transport-Security"),
+            content_security_policy: header("Content-Security-Policy"),
+            x_frame_options: header("X-Frame-Options"),
+        }
+    }
+
+    /// Returns the caching metadata of the response.
+    ///
+    /// # Example
+    /// ```
+    /// use curl_wrapper::CurlResponse;
+    /// use std::time::Duration;
+    ///
+    /// let output = b"HTTP/1.1 200 OK\r\nCache-Control: public, max-age=300\r\nAge: 60\r\n\r\n";
+    /// let policy = CurlResponse::new(output.to_vec()).cache_policy();
+    /// assert!(policy.is_cacheable());
+    /// assert_eq!(policy.remaining_freshness(), Some(Duration::from_secs(240)));
+    /// ```
+    pub fn cache_policy(&self) -> CachePolicy {
+        let mut policy = CachePolicy {
+            max_age: None,
+            no_cache: false,
+            no_store: false,
+            private: false,
+            age: self
+                .header("Age")
+                .and_then(|age| age.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or_default(),
+            expires: self
+                .header("Expires")
+                .map(|expires| httpdate::parse_http_date(expires).unwrap_or(UNIX_EPOCH)),
+            date: self
+                .header("Date")
+                .and_then(|date| httpdate::parse_http_date(date).ok()),
+        };
+        for directive in self.header("Cache-Control").unwrap_or_default().split(',') {
+            let (name, value) = directive.split_once('=').unwrap_or((directive, ""));
+            match name.trim().to_ascii_lowercase().as_str() {
+                "max-age" => {
+                    policy.max_age = value
+                        .trim()
+                        .trim_matches('"')
+                        .parse()
+                        .ok()
+                        .map(Duration::from_secs)
+                }
+                "no-cache" => policy.no_cache = true,
+                "no-store" => policy.no_store = true,
+                "private" => policy.private = true,
+                _ => {}
+            }
+        }
+        policy
+    }
+
+    /// Returns how many times larger the decoded body is than the bytes
+    /// received on the wire, or `None` if the response wasn't compressed or
+    /// its body wasn't decoded, by curl with `compressed(true)` or by the
+    /// `decode` feature.
+    ///
+    /// # Example
+    /// ```
+    /// use curl_wrapper::CurlResponse;
+    ///
+    /// let output = b"HTTP/1.1 200 OK\r\n\r\nHello, World!";
+    /// let response = CurlResponse::new(output.to_vec());
+    /// assert_eq!(response.compression_ratio(), None);
+    /// ```
+    pub fn compression_ratio(&self) -> Option<f64> {
+        let encoding = self.header("Content-Encoding")?;
+        if !self.decoded || encoding.eq_ignore_ascii_case("identity") {
+            return None;
+        }
+        match self.bytes_received {
+            0 => None,
+            size => Some(self.body_bytes.len() as f64 / size as f64),
+        }
+    }
+
+    /// Returns a reader over the whole body, including the part written to
+    /// disk with `buffer_limit`.
+    ///
+    /// # Example
+    /// ```
+    /// use curl_wrapper::CurlResponse;
+    /// use std::io::Read;
+    ///
+    /// let output = b"HTTP/1.1 200 OK\r\n\r\nHello, World!";
+    /// let mut body = String::new();
+    /// CurlResponse::new(output.to_vec()).body_reader().unwrap().read_to_string(&mut body).unwrap();
+    /// assert_eq!(body, "Hello, World!");
+    /// ```
+    pub fn body_reader(&self) -> io::Result<impl io::Read + '_> {
+        let spilled: Box<dyn io::Read> = match &self.spill {
+            Some(file) => Box::new(file.reopen()?),
+            None => Box::new(io::empty()),
+        };
+        Ok(io::Read::chain(self.body_bytes.as_slice(), spilled))
+    }
+
+    /// Returns at most the first `max_bytes` bytes of the body, cut at a
+    /// character boundary and followed by `...` if the body was truncated.
+    ///
+    /// # Example
+    /// ```
+    /// use curl_wrapper::CurlResponse;
+    ///
+    /// let output = b"HTTP/1.1 200 OK\r\n\r\nHello, World!";
+    /// let response = CurlResponse::new(output.to_vec());
+    /// assert_eq!(response.body_preview(5), "Hello...");
+    /// ```
+    pub fn body_preview(&self, max_bytes: usize) -> String {
+        if self.body.len() <= max_bytes {
+            return self.body.clone();
+        }
+        let mut end = max_bytes;
+        while !self.body.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}...", &self.body[..end])
+    }
+}
+
+#[cfg(test)]
+mod tests {
     use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    // Use cargo test -- --nocapture for printing output
+
+    /// Serves every connection on a local port with `handler`, which receives
+    /// the raw request and returns the raw response. Returns the base URL.
+    async fn serve<F>(handler: F) -> String
+    where
+        F: Fn(String) -> Vec<u8> + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handler = std::sync::Arc::new(handler);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    let request = read_request(&mut stream).await;
+                    let _ = stream.write_all(&handler(request)).await;
+                });
+            }
+        });
+        url
+    }
+
+    /// Reads a request's head and its `Content-Length` or chunked body.
+    async fn read_request(stream: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buf = [0; 4096];
+        let head_end = loop {
+            let n = stream.read(&mut buf).await.unwrap();
+            request.extend_from_slice(&buf[..n]);
+            if let Some(i) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                break i + 4;
+            }
+            if n == 0 {
+                return String::from_utf8_lossy(&request).to_string();
+            }
+        };
+        let head = String::from_utf8_lossy(&request[..head_end]).to_lowercase();
+        let length: usize = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .map_or(0, |length| length.trim().parse().unwrap());
+        let chunked = head.contains("transfer-encoding: chunked");
+        while request.len() < head_end + length
+            || (chunked && !request[head_end - 2..].ends_with(b"\r\n0\r\n\r\n"))
+        {
+            let n = stream.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        String::from_utf8_lossy(&request).to_string()
+    }
+
+    /// Builds a `200 OK` response with `body`.
+    fn ok(body: &str) -> Vec<u8> {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .into_bytes()
+    }
+
+    /// Builds a `200 OK` response echoing `request` with `\n` line endings.
+    fn echo(request: &str) -> Vec<u8> {
+        ok(&request.replace("\r\n", "\n"))
+    }
+
+    #[tokio::test]
+    async fn get() {
+        let url = serve(|request| echo(&request)).await;
+        let curl = Curl::new(&format!("{}/get", url))
+            .method(Method::GET)
+            .set_header("Content-Type: application/json")
+            .set_header("Cookie: test-cookie");
+        let response = curl.send().await.unwrap();
+        assert_eq!(response.status_code, 200);
+        assert!(response.body.starts_with("GET /get HTTP/1.1"));
+        assert!(response.body.contains("\nContent-Type: application/json\n"));
+        assert!(response.body.contains("\nCookie: test-cookie"));
+    }
+
+    #[tokio::test]
+    async fn post() {
+        let url = serve(|request| echo(&request)).await;
+        let curl = Curl::new(&format!("{}/post", url))
+            .method(Method::POST)
+            .set_header("Content-Type: application/json")
+            .set_header("Cookie: test-cookie");
+        let response = curl.send().await.unwrap();
+        assert_eq!(response.status_code, 200);
+        assert!(response.body.starts_with("POST /post HTTP/1.1"));
+        assert!(response.body.contains("\nContent-Type: application/json\n"));
+        assert!(response.body.contains("\nCookie: test-cookie"));
+    }
+
+    #[tokio::test]
+    async fn put() {
+        let url = serve(|request| echo(&request)).await;
+        let curl = Curl::new(&format!("{}/put", url))
+            .method(Method::PUT)
+            .set_header("Content-Type: application/json")
+            .set_header("Cookie: test-cookie");
+        let response = curl.send().await.unwrap();
+        assert_eq!(response.status_code, 200);
+        assert!(response.body.starts_with("PUT /put HTTP/1.1"));
+        assert!(response.body.contains("\nContent-Type: application/json\n"));
+        assert!(response.body.contains("\nCookie: test-cookie"));
+    }
+
+    #[tokio::test]
+    async fn delete() {
+        let url = serve(|request| echo(&request)).await;
+        let curl = Curl::new(&format!("{}/delete", url))
+            .method(Method::DELETE)
+            .set_header("Content-Type: application/json")
+            .set_header("Cookie: test-cookie");
+        let response = curl.send().await.unwrap();
+        assert_eq!(response.status_code, 200);
+        assert!(response.body.starts_with("DELETE /delete HTTP/1.1"));
+        assert!(response.body.contains("\nContent-Type: application/json\n"));
+        assert!(response.body.contains("\nCookie: test-cookie"));
+    }
+
+    #[tokio::test]
+    async fn redirect() {
+        let url = serve(|request| {
+            if request.starts_with("GET /redirect-to") {
+                return b"HTTP/1.1 302 Found\r\nLocation: /get\r\nContent-Length: 0\r\n\r\n"
+                    .to_vec();
+            }
+            echo(&request)
+        })
+        .await;
+        let curl = Curl::new(&format!("{}/redirect-to", url))
+            .set_header("Content-Type: application/json")
+            .redirects(true);
+        let response = curl.send().await.unwrap();
+        assert_eq!(response.status_code, 200);
+        assert!(response.body.starts_with("GET /get HTTP/1.1"));
+        assert_eq!(response.effective_url, Some(format!("{}/get", url)));
+    }
+
+    #[test]
+    fn socks5h() {
+        let curl = Curl::new("https://httpbin.org/get").socks5h("127.0.0.1:9050");
+        let args = curl.build_args().unwrap();
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--socks5-hostname", "127.0.0.1:9050"]));
+        assert!(!args.contains(&"--socks5".to_string()));
+    }
+
+    #[test]
+    fn socks5_with_proxy() {
+        let curl = Curl::new("https://httpbin.org/get")
+            .set_proxy("http://proxy.example.com:8080")
+            .socks5("127.0.0.1:1080");
+        let err = curl.build_args().unwrap_err();
+        assert!(matches!(err, CurlError::InvalidInput(_)));
+    }
+
+    #[test]
+    fn resolve() {
+        let curl = Curl::new("https://staging.example.com")
+            .resolve("staging.example.com", 443, "10.0.0.1")
+            .resolve("api.example.com", 80, "10.0.0.2");
+        let args = curl.build_args().unwrap();
+        let entries: Vec<&String> = args
+            .windows(2)
+            .filter(|w| w[0] == "--resolve")
+            .map(|w| &w[1])
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                "staging.example.com:443:10.0.0.1",
+                "api.example.com:80:10.0.0.2"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn hedge() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let (killed_tx, killed_rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            // The first connection stalls until the client goes away.
+            let (mut slow, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = slow.read(&mut buf).await.unwrap();
+            let slow_task = tokio::spawn(async move {
+                let closed = tokio::time::timeout(Duration::from_secs(5), async {
+                    while slow.read(&mut buf).await.unwrap() > 0 {}
+                })
+                .await
+                .is_ok();
+                let _ = killed_tx.send(closed);
+            });
+            let (mut fast, _) = listener.accept().await.unwrap();
+            let _ = fast.read(&mut buf).await.unwrap();
+            fast.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nfast")
+                .await
+                .unwrap();
+            slow_task.await.unwrap();
+        });
+
+        let curl = Curl::new(&url).hedge(Duration::from_millis(100));
+        let response = curl.send().await.unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, "fast");
+        assert!(killed_rx.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn hedge_body_from_iter() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let request = read_request(&mut stream).await;
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    let _ = stream.write_all(&echo(&request)).await;
+                });
+            }
+        });
+
+        let response = Curl::new(&url)
+            .method(Method::POST)
+            .body_from_iter(["a", "b"].into_iter().map(String::from))
+            .hedge(Duration::from_millis(50))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 200);
+        assert!(response.body.starts_with("POST / HTTP/1.1"));
+    }
+
+    #[tokio::test]
+    async fn block_private_ips() {
+        use futures::StreamExt;
+
+        for url in [
+            "http://169.254.169.254/latest/meta-data",
+            "http://10.0.0.1/",
+            "http://[::1]:8080/",
+            "http://[::ffff:192.168.1.1]/",
+            "http://localhost/",
+            "http://100.64.0.1/",
+            "http://[64:ff9b::a9fe:a9fe]/",
+            "http://[::10.0.0.1]/",
+        ] {
+            let err = Curl::new(url)
+                .block_private_ips(true)
+                .send()
+                .await
+                .unwrap_err();
+            assert!(matches!(err, CurlError::InvalidInput(_)), "{}", url);
+        }
+        let curl = Curl::new("https://example.com")
+            .resolve("example.com", 443, "10.0.0.5")
+            .block_private_ips(true);
+        assert!(matches!(curl.send().await, Err(CurlError::InvalidInput(_))));
+
+        let curl = Curl::new("http://93.184.215.14/").block_private_ips(true);
+        assert_eq!(curl.check_target().await.unwrap(), None);
+        let curl = Curl::new("https://example.com")
+            .resolve("example.com", 443, "93.184.215.14")
+            .block_private_ips(true);
+        assert_eq!(curl.check_target().await.unwrap(), None);
+
+        let url = serve(|_| ok("local")).await;
+        let response = Curl::new(&url).send().await.unwrap();
+        assert_eq!(response.body, "local");
+
+        // The streaming paths are guarded too.
+        let results: Vec<_> = Curl::new(&url)
+            .block_private_ips(true)
+            .send_stream()
+            .collect()
+            .await;
+        assert_eq!(results.len(), 1);
+        assert!(matches!(results[0], Err(CurlError::InvalidInput(_))));
+        #[cfg(feature = "json")]
+        {
+            let results: Vec<_> = Curl::new(&url)
+                .block_private_ips(true)
+                .send_json_array_stream::<serde_json::Value>()
+                .collect()
+                .await;
+            assert_eq!(results.len(), 1);
+            assert!(matches!(results[0], Err(CurlError::InvalidInput(_))));
+        }
+
+        // Redirects and proxies would bypass the check.
+        let curl = Curl::new("https://example.com").block_private_ips(true);
+        for curl in [
+            curl.clone().redirects(true),
+            curl.clone().set_proxy("http://proxy.example.com:8080"),
+            curl.clone().socks5("proxy.example.com:1080"),
+            curl.clone().socks5h("proxy.example.com:1080"),
+        ] {
+            assert!(matches!(curl.build_args(), Err(CurlError::InvalidInput(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn invalid_url() {
+        for url in ["", "example.com", "/items?page=2", "http://"] {
+            let curl = Curl::new(url).curl_path("/nonexistent/curl");
+            let err = curl.send().await.unwrap_err();
+            assert!(matches!(err, CurlError::InvalidInput(_)), "{:?}", url);
+        }
+    }
+
+    #[tokio::test]
+    async fn method_with_body() {
+        let url = serve(|request| echo(&request)).await;
+        let response = Curl::new(&format!("{}/delete", url))
+            .method(Method::DELETE)
+            .set_header("Content-Type: application/json")
+            .set_body(r#"{"id": 1}"#)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.body.starts_with("DELETE /delete HTTP/1.1"));
+        assert!(response.body.ends_with(r#"{"id": 1}"#));
+
+        let get = Curl::new(&url).set_body("body");
+        assert!(get.build_args().is_ok());
+        let err = get.safe_mode(true).send().await.unwrap_err();
+        assert!(matches!(err, CurlError::InvalidInput(_)));
+    }
+
+    #[tokio::test]
+    async fn strict_parse() {
+        let url = serve(|_| b"\x00\xffgarbage\r\nmore garbage".to_vec()).await;
+        let curl = Curl::new(&url).raw_arg("--http0.9");
+        let response = curl.clone().send().await.unwrap();
+        assert_eq!(response.status_code, 0);
+
+        let err = curl.strict_parse(true).send().await.unwrap_err();
+        match err {
+            CurlError::ParseError { offset, snippet } => {
+                assert_eq!(offset, 0);
+                assert_eq!(snippet, "\0\u{fffd}garbage\r\nmore garbage");
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn auth() {
+        let args = Curl::new("https://example.com")
+            .auth(AuthMethod::Digest, "u", "p")
+            .build_args()
+            .unwrap();
+        assert!(args.windows(3).any(|w| w == ["--digest", "--user", "u:p"]));
+
+        let args = Curl::new("https://example.com")
+            .auth(AuthMethod::Bearer, "", "token")
+            .build_args()
+            .unwrap();
+        assert!(args.windows(2).any(|w| w == ["--oauth2-bearer", "token"]));
+        assert!(!args.contains(&"--user".to_string()));
+    }
+
+    #[tokio::test]
+    async fn clear_proxy_env() {
+        let url = serve(|_| ok("hello")).await;
+        let curl = Curl::new(&url).env("http_proxy", "http://127.0.0.1:1");
+        assert!(curl.clone().send().await.is_err());
+
+        let response = curl.clear_proxy_env(true).send().await.unwrap();
+        assert_eq!(response.body, "hello");
+    }
+
+    #[test]
+    fn local_port() {
+        let args = Curl::new("https://example.com")
+            .interface("192.168.1.5")
+            .local_port("5000-5100")
+            .build_args()
+            .unwrap();
+        assert!(args.windows(2).any(|w| w == ["--interface", "192.168.1.5"]));
+        assert!(args.windows(2).any(|w| w == ["--local-port", "5000-5100"]));
+
+        assert!(Curl::new("https://example.com")
+            .local_port("5000")
+            .build_args()
+            .is_ok());
+        for range in ["", "0", "5100-5000", "5000-70000", "5000-", "a-b"] {
+            let curl = Curl::new("https://example.com").local_port(range);
+            assert!(curl.build_args().is_err(), "{:?}", range);
+        }
+    }
+
+    #[test]
+    fn is_parsed() {
+        let response = CurlResponse::new(b"\x16\x03\x01\x02\x00\x01\x00".to_vec());
+        assert!(!response.is_parsed());
+        assert_eq!(response.status_code, 0);
+
+        let response = CurlResponse::new(b"HTTP/1.1 200 OK\r\nContent-Type: text".to_vec());
+        assert!(!response.is_parsed());
+
+        let response = CurlResponse::new(b"HTTP/1.1 200 OK\r\n\r\nhello".to_vec());
+        assert!(response.is_parsed());
+    }
+
+    #[test]
+    fn parse_arbitrary_bytes() {
+        // A deterministic xorshift generator, so failures are reproducible.
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        let seeds: [&[u8]; 5] = [
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello",
+            b"HTTP/1.1 100 Continue\r\n\r\nHTTP/2 301\r\nLocation: /\r\n\r\n",
+            "HTTP/1.1 ٢٠٠ OK\r\n\r\n".as_bytes(),
+            b"HTTP/1.1 206\r\nContent-Range: bytes 0-1/2\r\nCache-Control: max-age=x\r\n\r\n",
+            b"\n--curl-wrapper-write-out--\ntime_total=1e400\n",
+        ];
+        for _ in 0..2000 {
+            let seed = seeds[next() as usize % seeds.len()];
+            let mut input = seed.to_vec();
+            for _ in 0..next() % 8 {
+                let i = next() as usize % (input.len() + 1);
+                match next() % 3 {
+                    0 if i < input.len() => input[i] = next() as u8,
+                    1 if i < input.len() => {
+                        input.remove(i);
+                    }
+                    _ => input.insert(i, next() as u8),
+                }
+            }
+            let response = CurlResponse::new(input);
+            let _ = response.content_range();
+            let _ = response.cache_policy();
+            let _ = response.joined_header("Content-Length");
+        }
+    }
+
+    #[tokio::test]
+    async fn compression_algorithms() {
+        let url = serve(|request| echo(&request)).await;
+        let curl = Curl::new(&url).compression_algorithms(&["gzip"]);
+        assert!(curl
+            .build_args()
+            .unwrap()
+            .contains(&"--compressed".to_string()));
+        let response = curl.send().await.unwrap();
+        assert!(response.body.ends_with("\nAccept-Encoding: gzip"));
+    }
+
+    #[tokio::test]
+    async fn send_text() {
+        let url = serve(|request| {
+            if request.starts_with("GET /missing") {
+                b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec()
+            } else {
+                ok(r#"{"hello": "world"}"#)
+            }
+        })
+        .await;
+        let body = Curl::new(&format!("{}/get", url))
+            .send_text()
+            .await
+            .unwrap();
+        assert_eq!(body, r#"{"hello": "world"}"#);
+        let err = Curl::new(&format!("{}/missing", url))
+            .send_text()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CurlError::Http { status_code: 404 }));
+    }
+
+    #[tokio::test]
+    async fn send_cancellable() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let (closed_tx, closed_rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            // The request stalls until the client goes away.
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            let closed = tokio::time::timeout(Duration::from_secs(5), async {
+                while stream.read(&mut buf).await.unwrap() > 0 {}
+            })
+            .await
+            .is_ok();
+            let _ = closed_tx.send(closed);
+        });
+
+        let token = CancellationToken::new();
+        tokio::spawn({
+            let token = token.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                token.cancel();
+            }
+        });
+        let err = Curl::new(&url).send_cancellable(token).await.unwrap_err();
+        assert!(matches!(err, CurlError::Cancelled));
+        assert!(closed_rx.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn curl_path() {
+        let curl = Curl::new("https://httpbin.org/get").curl_path("/nonexistent/curl");
+        let err = curl.send().await.unwrap_err();
+        assert!(matches!(err, CurlError::Io(e) if e.kind() == io::ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn build_args() {
+        let curl = Curl::new("https://httpbin.org/post")
+            .method(Method::POST)
+            .set_header("Content-Type: application/json")
+            .set_body("{\"hello\": \"world\"}")
+            .set_proxy("http://proxy.example.com:8080")
+            .redirects(true)
+            .compressed(true)
+            .interface("eth0");
+        assert_eq!(
+            curl.build_args().unwrap(),
+            [
+                "--silent",
+                "--show-error",
+                "--include",
+                "--suppress-connect-headers",
+                "--interface",
+                "eth0",
+                "-L",
+                "-X",
+                "POST",
+                "--proxy",
+                "http://proxy.example.com:8080",
+                "--url",
+                "https://httpbin.org/post",
+                "-H",
+                "Content-Type: application/json",
+                "-d",
+                "{\"hello\": \"world\"}",
+                "--compressed",
+                "--write-out",
+                "\n--curl-wrapper-write-out--\nsize_upload=%{size_upload}\nsize_download=%{size_download}\nurl_effective=%{url_effective}\n",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn option_like_input() {
+        let output =
+            std::env::temp_dir().join(format!("curl-wrapper-injected-{}", std::process::id()));
+        let url = format!("-o{}", output.display());
+        let curl = Curl::new(&url);
+        let args = curl.build_args().unwrap();
+        assert!(args.windows(2).any(|w| w == ["--url", url.as_str()]));
+        assert!(curl.send().await.is_err());
+        assert!(!output.exists());
+
+        for header in ["-o/etc/passwd", "no colon", ": value"] {
+            let curl = Curl::new("https://example.com").set_header(header);
+            assert!(matches!(curl.build_args(), Err(CurlError::InvalidInput(_))));
+        }
+        let curl = Curl::new("https://example.com").set_header("X-Empty;");
+        assert!(curl.build_args().is_ok());
+    }
+
+    #[test]
+    fn to_command_string() {
+        let curl = Curl::new("https://httpbin.org/get")
+            .set_header("Cookie: it's")
+            .set_body("");
+        assert_eq!(
+            curl.to_command_string().unwrap(),
+            "curl --silent --show-error --include --suppress-connect-headers -X GET --url https://httpbin.org/get -H 'Cookie: it'\\''s' -d '' --write-out '\n--curl-wrapper-write-out--\nsize_upload=%{size_upload}\nsize_download=%{size_download}\n'"
+        );
+    }
+
+    #[test]
+    fn body_preview() {
+        let response = CurlResponse::new("HTTP/1.1 200 OK\r\n\r\nhéllo wörld".as_bytes().to_vec());
+        assert_eq!(response.body_preview(100), "héllo wörld");
+        // 'é' spans bytes 1..3, so a 2-byte preview stops before it.
+        assert_eq!(response.body_preview(2), "h...");
+        assert_eq!(response.body_preview(3), "hé...");
+    }
+
+    #[cfg(feature = "decode")]
+    #[tokio::test]
+    async fn compression_ratio() {
+        use std::io::Write;
+
+        // Trailing whitespace is trimmed from `body` but not from `body_bytes`.
+        let body = format!("{}\n\n", "a".repeat(1000));
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(body.as_bytes()).unwrap();
+        let gzip = encoder.finish().unwrap();
+        let url = serve({
+            let gzip = gzip.clone();
+            move |_| {
+                let mut response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    gzip.len()
+                )
+                .into_bytes();
+                response.extend_from_slice(&gzip);
+                response
+            }
+        })
+        .await;
+        let response = Curl::new(&url).compressed(true).send().await.unwrap();
+        assert_eq!(response.body_bytes, body.as_bytes());
+        assert_eq!(response.bytes_received, gzip.len() as u64);
+        assert_eq!(
+            response.compression_ratio(),
+            Some(body.len() as f64 / gzip.len() as f64)
+        );
+
+        let url = serve(|_| ok(&"a".repeat(1000))).await;
+        let response = Curl::new(&url).compressed(true).send().await.unwrap();
+        assert_eq!(response.compression_ratio(), None);
+    }
+
+    #[tokio::test]
+    async fn compression_ratio_undecoded() {
+        // `Hello, World! Hello, World! Hello, World!`, gzipped.
+        const GZIP: [u8; 37] = [
+            31, 139, 8, 0, 0, 0, 0, 0, 2, 3, 243, 72, 205, 201, 201, 215, 81, 8, 207, 47, 202, 73,
+            81, 84, 240, 192, 205, 3, 0, 204, 98, 131, 118, 41, 0, 0, 0,
+        ];
+        let url = serve(|_| {
+            let mut response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                GZIP.len()
+            )
+            .into_bytes();
+            response.extend_from_slice(&GZIP);
+            response
+        })
+        .await;
+        // Without `compressed(true)`, only the `decode` feature decodes it.
+        let response = Curl::new(&url).send().await.unwrap();
+        if cfg!(feature = "decode") {
+            assert_eq!(response.body, "Hello, World! Hello, World! Hello, World!");
+            assert_eq!(response.compression_ratio(), Some(41.0 / 37.0));
+        } else {
+            assert_eq!(response.body_bytes, GZIP);
+            assert_eq!(response.compression_ratio(), None);
+        }
+    }
+
+    #[test]
+    fn clone() {
+        let base = Curl::new("https://httpbin.org/get")
+            .set_header("Accept: application/json")
+            .interface("eth0");
+        let post = base.clone().method(Method::POST);
+        assert_eq!(base.method, None);
+        assert_eq!(post.method, Some(Method::POST));
+        assert_eq!(base.headers, post.headers);
+    }
+
+    #[tokio::test]
+    async fn timing() {
+        let url = serve(|_| ok("Hello, World!")).await;
+        let response = Curl::new(&url).with_timing(true).send().await.unwrap();
+        assert_eq!(response.body, "Hello, World!");
+        let timing = response.timing.unwrap();
+        assert!(timing.connect >= timing.namelookup);
+        assert!(timing.total >= timing.connect);
+        assert!(timing.total > Duration::ZERO);
+
+        let response = Curl::new(&url).send().await.unwrap();
+        assert!(response.timing.is_none());
+    }
+
+    #[tokio::test]
+    async fn cookie_store() {
+        let url = serve(|request| {
+            if request.starts_with("GET /login") {
+                b"HTTP/1.1 200 OK\r\nSet-Cookie: session=abc; Path=/\r\nContent-Length: 0\r\n\r\n"
+                    .to_vec()
+            } else {
+                echo(&request)
+            }
+        })
+        .await;
+        let store = Arc::new(Mutex::new(CookieStore::new()));
+        let login = Curl::new(&format!("{}/login", url)).cookie_store(store.clone());
+        login.send().await.unwrap();
+
+        let profile = Curl::new(&format!("{}/profile", url)).cookie_store(store.clone());
+        let response = profile.send().await.unwrap();
+        assert!(response.body.contains("Cookie: session=abc"));
+
+        let other = Curl::new("http://other.example.com/").cookie_store(store);
+        assert!(!other
+            .build_args()
+            .unwrap()
+            .iter()
+            .any(|a| a.starts_with("Cookie:")));
+    }
+
+    #[tokio::test]
+    async fn cookie_store_redirects() {
+        let url = serve(|request| {
+            if request.starts_with("GET /login") {
+                b"HTTP/1.1 302 Found\r\nSet-Cookie: session=abc\r\nLocation: /home\r\nContent-Length: 0\r\n\r\n"
+                    .to_vec()
+            } else {
+                echo(&request)
+            }
+        })
+        .await;
+        let store = Arc::new(Mutex::new(CookieStore::new()));
+        let response = Curl::new(&format!("{}/login", url))
+            .redirects(true)
+            .cookie_store(store.clone())
+            .send()
+            .await
+            .unwrap();
+        // The cookie set by the redirect is sent to the next hop.
+        assert!(response.body.starts_with("GET /home HTTP/1.1"));
+        assert!(response.body.contains("Cookie: session=abc"));
+
+        let response = Curl::new(&format!("{}/home", url))
+            .cookie_store(store)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.body.contains("Cookie: session=abc"));
+    }
+
+    #[test]
+    fn cookie_store_public_suffix() {
+        let mut store = CookieStore::new();
+        store.store(
+            "http://evil.com/",
+            &["Set-Cookie: track=1; Domain=com".to_string()],
+        );
+        store.store(
+            "http://evil.co.uk/",
+            &["Set-Cookie: track=2; Domain=co.uk".to_string()],
+        );
+        assert_eq!(store.cookie_header("http://bank.com/"), None);
+        assert_eq!(store.cookie_header("http://bank.co.uk/"), None);
+
+        store.store(
+            "http://www.example.com/",
+            &["Set-Cookie: a=1; Domain=example.com".to_string()],
+        );
+        assert_eq!(
+            store.cookie_header("http://api.example.com/").as_deref(),
+            Some("a=1")
+        );
+
+        // A public suffix naming the host itself makes a host-only cookie.
+        store.store(
+            "http://localhost/",
+            &["Set-Cookie: b=2; Domain=localhost".to_string()],
+        );
+        assert_eq!(
+            store.cookie_header("http://localhost/").as_deref(),
+            Some("b=2")
+        );
+
+        // IP addresses have no subdomains, so `10.0.0.1` isn't within `0.0.1`.
+        store.store(
+            "http://127.0.0.1/",
+            &["Set-Cookie: c=3; Domain=0.0.1".to_string()],
+        );
+        assert_eq!(store.cookie_header("http://10.0.0.1/"), None);
+    }
+
+    #[test]
+    fn cookie_store_expires() {
+        let mut store = CookieStore::new();
+        store.store(
+            "http://example.com/",
+            &[
+                "Set-Cookie: session=abc".to_string(),
+                "Set-Cookie: theme=dark; Expires=Fri, 01 Jan 2100 00:00:00 GMT".to_string(),
+            ],
+        );
+        assert_eq!(
+            store.cookie_header("http://example.com/").as_deref(),
+            Some("session=abc; theme=dark")
+        );
+
+        // What Express's `clearCookie` sends.
+        store.store(
+            "http://example.com/",
+            &["Set-Cookie: session=; Path=/; Expires=Thu, 01 Jan 1970 00:00:00 GMT".to_string()],
+        );
+        assert_eq!(
+            store.cookie_header("http://example.com/").as_deref(),
+            Some("theme=dark")
+        );
+
+        // Max-Age wins over Expires.
+        store.store(
+            "http://example.com/",
+            &["Set-Cookie: theme=; Max-Age=0; Expires=Fri, 01 Jan 2100 00:00:00 GMT".to_string()],
+        );
+        assert_eq!(store.cookie_header("http://example.com/"), None);
+    }
+
+    #[tokio::test]
+    async fn verbose() {
+        let url = serve(|_| ok("Hello, World!")).await;
+        let response = Curl::new(&url).verbose(true).send().await.unwrap();
+        assert_eq!(response.body, "Hello, World!");
+        assert!(response.trace.unwrap().contains("> GET / HTTP/1.1"));
+
+        let response = Curl::new(&url).send().await.unwrap();
+        assert!(response.trace.is_none());
+    }
+
+    #[tokio::test]
+    async fn body_base64() {
+        let url = serve(|request| echo(&request)).await;
+        let data = [0xfb, 0xff, 0x00, 0x10];
+        let response = Curl::new(&url)
+            .method(Method::POST)
+            .set_body_base64(&data)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.body.ends_with("\n\n-_8AEA"));
+
+        let response = Curl::new(&url)
+            .method(Method::POST)
+            .set_body_base64_padded(&data)
+            .send()
+            .await
+            .unwrap();
+        assert!(response.body.ends_with("\n\n-_8AEA=="));
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn into_http_response() {
+        let output =
+            b"HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nBad Header\r\n\r\nmissing";
+        let response: http::Response<Vec<u8>> = CurlResponse::new(output.to_vec()).into();
+        assert_eq!(response.status(), http::StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()["content-type"], "text/plain");
+        assert_eq!(response.headers().len(), 1);
+        assert_eq!(response.body(), b"missing");
+    }
+
+    #[cfg(feature = "http")]
+    #[tokio::test]
+    async fn into_http_response_binary() {
+        let url = serve(|_| {
+            let mut response = b"HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n".to_vec();
+            response.extend([0xff, 0x00, b'\n']);
+            response
+        })
+        .await;
+        let response: http::Response<Vec<u8>> = Curl::new(&url).send().await.unwrap().into();
+        assert_eq!(response.body(), &[0xff, 0x00, b'\n']);
+    }
+
+    #[test]
+    fn ip_version() {
+        let curl = Curl::new("https://httpbin.org/get").ipv4(true);
+        let args = curl.build_args().unwrap();
+        assert!(args.contains(&"-4".to_string()));
+        assert!(!args.contains(&"-6".to_string()));
+
+        let curl = curl.ipv6(true);
+        let err = curl.build_args().unwrap_err();
+        assert!(matches!(err, CurlError::InvalidInput(_)));
+    }
+
+    #[tokio::test]
+    async fn cookie_jar() {
+        let url = serve(|request| {
+            if request.starts_with("GET /login") {
+                b"HTTP/1.1 200 OK\r\nSet-Cookie: session=abc; Path=/\r\nContent-Length: 0\r\n\r\n"
+                    .to_vec()
+            } else {
+                echo(&request)
+            }
+        })
+        .await;
+        let jar = std::env::temp_dir().join(format!("curl-wrapper-jar-{}", std::process::id()));
+        let jar = jar.to_str().unwrap();
+
+        Curl::new(&format!("{}/login", url))
+            .cookie_file(jar)
+            .cookie_jar(jar)
+            .send()
+            .await
+            .unwrap();
+        let response = Curl::new(&format!("{}/profile", url))
+            .cookie_file(jar)
+            .cookie_jar(jar)
+            .send()
+            .await
+            .unwrap();
+        std::fs::remove_file(jar).unwrap();
+        assert!(response.body.contains("Cookie: session=abc"));
+    }
+
+    #[test]
+    fn canonicalize_headers() {
+        let curl = Curl::new("https://httpbin.org/get")
+            .set_header("content-type: x")
+            .set_header("x-REQUEST-id:  Mixed-Case Value");
+        let args = curl
+            .clone()
+            .canonicalize_headers(true)
+            .build_args()
+            .unwrap();
+        assert!(args.contains(&"Content-Type: x".to_string()));
+        assert!(args.contains(&"X-Request-Id:  Mixed-Case Value".to_string()));
+
+        let args = curl.build_args().unwrap();
+        assert!(args.contains(&"content-type: x".to_string()));
+    }
+
+    #[tokio::test]
+    async fn cookie() {
+        let url = serve(|request| echo(&request)).await;
+        let response = Curl::new(&format!("{}/cookies", url))
+            .cookie("session", "abc")
+            .cookie("theme", "dark")
+            .send()
+            .await
+            .unwrap();
+        assert!(response.body.contains("Cookie: session=abc; theme=dark"));
+
+        // Cookies from a store are sent along.
+        let store = Arc::new(Mutex::new(CookieStore::new()));
+        store
+            .lock()
+            .unwrap()
+            .store(&url, &["Set-Cookie: session=abc".to_string()]);
+        let response = Curl::new(&format!("{}/cookies", url))
+            .cookie_store(store)
+            .cookie("theme", "dark")
+            .send()
+            .await
+            .unwrap();
+        assert!(response.body.contains("Cookie: session=abc; theme=dark"));
+        assert_eq!(response.body.matches("Cookie:").count(), 1);
+    }
+
+    #[test]
+    fn security_headers() {
+        let output = b"HTTP/1.1 200 OK\r\nstrict-transport-security: max-age=31536000\r\nContent-Security-Policy: default-src 'self'\r\n\r\n";
+        let security = CurlResponse::new(output.to_vec()).security_headers();
+        assert_eq!(
+            security.strict_transport_security.as_deref(),
+            Some("max-age=31536000")
+        );
+        assert_eq!(
+            security.content_security_policy.as_deref(),
+            Some("default-src 'self'")
+        );
+        assert_eq!(security.x_frame_options, None);
+        assert_eq!(security.missing(), ["X-Frame-Options"]);
+    }
+
+    #[tokio::test]
+    async fn fail_on_error() {
+        let url = serve(|_| {
+            b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 4\r\n\r\noops".to_vec()
+        })
+        .await;
+        let err = Curl::new(&format!("{}/status/500", url))
+            .fail_on_error(true)
+            .send()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CurlError::Http { status_code: 500 }));
+
+        let response = Curl::new(&format!("{}/status/500", url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 500);
+    }
+
+    #[tokio::test]
+    async fn connection_failed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        drop(listener);
+        let err = Curl::new(&url)
+            .fail_on_error(true)
+            .send()
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            CurlError::Curl {
+                exit_code: Some(7),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn deadline_source() {
+        struct Fixed(Option<Duration>);
+
+        impl Deadline for Fixed {
+            fn remaining(&self) -> Option<Duration> {
+                self.0
+            }
+        }
+
+        let curl = Curl::new("https://httpbin.org/get")
+            .with_deadline_source(Fixed(Some(Duration::from_millis(2500))));
+        let args = curl.build_args().unwrap();
+        assert!(args.windows(2).any(|w| w == ["--max-time", "2.500"]));
+
+        let curl = Curl::new("https://httpbin.org/get").with_deadline_source(Fixed(None));
+        let args = curl.build_args().unwrap();
+        assert!(!args.contains(&"--max-time".to_string()));
+
+        let curl =
+            Curl::new("https://httpbin.org/get").with_deadline_source(Fixed(Some(Duration::ZERO)));
+        let err = curl.build_args().unwrap_err();
+        assert!(matches!(err, CurlError::InvalidInput(_)));
+    }
+
+    #[tokio::test]
+    async fn range() {
+        let url = serve(|request| {
+            let resource = "0123456789".repeat(100);
+            if request.contains("Range: bytes=0-99") {
+                format!(
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 0-99/1000\r\nContent-Length: 100\r\n\r\n{}",
+                    &resource[..100]
+                )
+                .into_bytes()
+            } else {
+                ok(&resource)
+            }
+        })
+        .await;
+        let response = Curl::new(&url).range(0, Some(99)).send().await.unwrap();
+        assert_eq!(response.status_code, 206);
+        assert_eq!(response.body.len(), 100);
+
+        let args = Curl::new(&url).range(100, None).build_args().unwrap();
+        assert!(args.windows(2).any(|w| w == ["-r", "100-"]));
+    }
+
+    #[tokio::test]
+    async fn unix_socket() {
+        let path = std::env::temp_dir().join(format!("curl-wrapper-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = tokio::net::UnixListener::bind(&path).unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = stream.read(&mut buf).await.unwrap();
+            stream.write_all(&ok("from the socket")).await.unwrap();
+        });
+
+        let response = Curl::new("http://localhost/info")
+            .unix_socket(path.to_str().unwrap())
+            .send()
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, "from the socket");
+    }
+
+    #[test]
+    fn content_range() {
+        let output = b"HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 200-1023/1024\r\n\r\n";
+        let range = CurlResponse::new(output.to_vec()).content_range().unwrap();
+        assert_eq!(range.start, 200);
+        assert_eq!(range.end, 1023);
+        assert_eq!(range.total, Some(1024));
+
+        let output = b"HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 0-99/*\r\n\r\n";
+        let range = CurlResponse::new(output.to_vec()).content_range().unwrap();
+        assert_eq!(range.total, None);
 
-    // Use cargo test -- --nocapture for printing output
+        let output = b"HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */1024\r\n\r\n";
+        assert_eq!(CurlResponse::new(output.to_vec()).content_range(), None);
+    }
+
+    #[test]
+    fn safe_mode() {
+        let conflict = Curl::new("https://httpbin.org/post")
+            .method(Method::POST)
+            .set_header("Transfer-Encoding: chunked")
+            .set_header("Content-Length: 4")
+            .set_body("test");
+        assert!(conflict.build_args().is_ok());
+        let err = conflict.safe_mode(true).build_args().unwrap_err();
+        assert!(matches!(err, CurlError::InvalidInput(_)));
+
+        let crlf = Curl::new("https://httpbin.org/get")
+            .set_header("X-Test: a\r\nContent-Length: 0")
+            .safe_mode(true);
+        let err = crlf.build_args().unwrap_err();
+        assert!(matches!(err, CurlError::InvalidInput(_)));
+
+        let lengths = Curl::new("https://httpbin.org/post")
+            .set_header("Content-Length: 4")
+            .set_header("content-length: 5")
+            .safe_mode(true);
+        assert!(lengths.build_args().is_err());
+
+        let safe = Curl::new("https://httpbin.org/get")
+            .set_header("Accept: application/json")
+            .safe_mode(true);
+        assert!(safe.build_args().is_ok());
+    }
 
+    #[test]
+    fn compressed_body() {
+        let gzip = [0x1f, 0x8b, 0x08, 0x00, 0xff, 0xfe, 0x0d, 0x0a, 0x0d, 0x0a];
+        let mut output = b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\n\r\n".to_vec();
+        output.extend_from_slice(&gzip);
+        let response = CurlResponse::new(output);
+        assert_eq!(response.body_bytes, gzip);
+        assert!(response.is_compressed());
+    }
+
+    #[cfg(feature = "decode")]
     #[tokio::test]
-    async fn get() {
-        let curl = Curl::new("https://httpbin.org/get")
-            .method(Method::GET)
-            .set_header("Content-Type: application/json")
-            .set_header("Cookie: test-cookie");
+    async fn decode_once() {
+        use std::io::Write;
+
+        let gzip = |data: &[u8]| {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data).unwrap();
+            encoder.finish().unwrap()
+        };
+        // A `.gz` file served with `Content-Encoding: gzip`.
+        let file = gzip(b"Hello, World!");
+        let url = serve({
+            let wire = gzip(&file);
+            move |_| {
+                let mut response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    wire.len()
+                )
+                .into_bytes();
+                response.extend_from_slice(&wire);
+                response
+            }
+        })
+        .await;
+        let response = Curl::new(&url).compressed(true).send().await.unwrap();
+        assert_eq!(response.body_bytes, file);
+        assert!(!response.is_compressed());
+
+        let response = Curl::new(&url)
+            .compressed(true)
+            .capture_wire(true)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.body_bytes, file);
+
+        // A body that fails to decode is kept as received.
+        let output = b"HTTP/1.1 200 OK\r\nContent-Encoding: br\r\n\r\n\xff\xfe".to_vec();
+        let response = CurlResponse::new(output);
+        assert_eq!(response.body_bytes, b"\xff\xfe");
+        assert!(response.is_compressed());
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn decode_gzip_body() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"Hello, compressed world!").unwrap();
+        let mut output = b"HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\n\r\n".to_vec();
+        output.extend_from_slice(&encoder.finish().unwrap());
+
+        let response = CurlResponse::new(output);
+        assert!(!response.is_compressed());
+        assert_eq!(response.body, "Hello, compressed world!");
+        assert_eq!(response.body_bytes, b"Hello, compressed world!");
+    }
+
+    #[cfg(feature = "decode")]
+    #[tokio::test]
+    async fn capture_wire() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"Hello, compressed world!").unwrap();
+        let gzip = encoder.finish().unwrap();
+        let url = serve({
+            let gzip = gzip.clone();
+            move |_| {
+                let mut response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    gzip.len()
+                )
+                .into_bytes();
+                response.extend_from_slice(&gzip);
+                response
+            }
+        })
+        .await;
+        let curl = Curl::new(&url).compressed(true).capture_wire(true);
+        let args = curl.build_args().unwrap();
+        assert!(!args.contains(&"--compressed".to_string()));
+        assert!(args.contains(&"Accept-Encoding: gzip, deflate, br".to_string()));
         let response = curl.send().await.unwrap();
-        println!("status code: {:?}", response.status_code);
-        println!("body: {}", response.body);
-        println!("headers: {:?}", response.headers);
+        assert_eq!(response.body_bytes, b"Hello, compressed world!");
+        assert_eq!(response.wire_bytes, Some(gzip));
+
+        let response = Curl::new(&url).compressed(true).send().await;
+        assert_eq!(response.unwrap().wire_bytes, None);
     }
 
     #[tokio::test]
-    async fn post() {
-        let curl = Curl::new("https://httpbin.org/post")
+    async fn started_at() {
+        let url = serve(|_| ok("hello")).await;
+        let response = Curl::new(&url).with_timing(true).send().await.unwrap();
+        let elapsed = response
+            .finished_at
+            .duration_since(response.started_at)
+            .unwrap();
+        let total = response.timing.unwrap().total;
+        assert!(elapsed >= total);
+        assert!(elapsed < total + Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn buffer_limit() {
+        use std::io::Read;
+
+        let body: String = (0..10_000).map(|i| format!("{}\n", i)).collect();
+        let url = serve({
+            let body = body.clone();
+            move |_| ok(&body)
+        })
+        .await;
+        let response = Curl::new(&url)
+            .buffer_limit(1024)
+            .redirects(true)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body_bytes.len(), 1024);
+        assert_eq!(response.effective_url, Some(format!("{}/", url)));
+        let mut read = String::new();
+        response
+            .body_reader()
+            .unwrap()
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, body);
+
+        // The limit is smaller than the headers of both responses.
+        let redirect = serve({
+            let body = body.clone();
+            move |request| {
+                if request.starts_with("GET /final ") {
+                    return ok(&body);
+                }
+                b"HTTP/1.1 302 Found\r\nLocation: /final\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_vec()
+            }
+        })
+        .await;
+        let response = Curl::new(&redirect)
+            .buffer_limit(16)
+            .redirects(true)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.history.len(), 2);
+        assert_eq!(response.body_bytes, &body.as_bytes()[..16]);
+        let mut read = String::new();
+        response
+            .body_reader()
+            .unwrap()
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, body);
+
+        let response = Curl::new(&url).buffer_limit(1 << 20).send().await.unwrap();
+        assert_eq!(response.body_bytes, body.as_bytes());
+        let mut read = String::new();
+        response
+            .body_reader()
+            .unwrap()
+            .read_to_string(&mut read)
+            .unwrap();
+        assert_eq!(read, body);
+    }
+
+    #[tokio::test]
+    async fn max_size() {
+        let url = serve(|_| ok(&"a".repeat(1000))).await;
+        let err = Curl::new(&url).max_size(100).send().await.unwrap_err();
+        assert!(matches!(
+            err,
+            CurlError::Curl {
+                exit_code: Some(63),
+                ..
+            }
+        ));
+
+        let response = Curl::new(&url).max_size(1000).send().await.unwrap();
+        assert_eq!(response.body.len(), 1000);
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn json_array_stream() {
+        use futures::StreamExt;
+
+        let url = serve(|request| {
+            if request.starts_with("GET /missing") {
+                return b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec();
+            }
+            let items: Vec<String> = (0..1000)
+                .map(|i| format!(r#"{{"id": {}, "tags": ["a,b", "]"]}}"#, i))
+                .collect();
+            ok(&format!("[{}]", items.join(",\n")))
+        })
+        .await;
+
+        let items: Vec<serde_json::Value> = Curl::new(&format!("{}/items", url))
+            .send_json_array_stream()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(items.len(), 1000);
+        for (i, item) in items.iter().enumerate() {
+            assert_eq!(item["id"], i);
+            assert_eq!(item["tags"][1], "]");
+        }
+
+        let results: Vec<Result<serde_json::Value, CurlError>> =
+            Curl::new(&format!("{}/missing", url))
+                .send_json_array_stream()
+                .collect()
+                .await;
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            results[0],
+            Err(CurlError::Http { status_code: 404 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_stream() {
+        use futures::StreamExt;
+
+        let url = serve(|request| {
+            if request.starts_with("GET /missing") {
+                return b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n".to_vec();
+            }
+            ok("{\"id\": 0}\n{\"id\": 1}\r\n{\"id\": 2}\n{\"id\": 3}\n{\"id\": 4}\n")
+        })
+        .await;
+
+        let lines: Vec<String> = Curl::new(&format!("{}/stream/5", url))
+            .send_stream()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[1], r#"{"id": 1}"#);
+
+        let results: Vec<_> = Curl::new(&format!("{}/missing", url))
+            .send_stream()
+            .collect()
+            .await;
+        assert_eq!(results.len(), 1);
+        assert!(matches!(
+            results[0],
+            Err(CurlError::Http { status_code: 404 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_stream_verbose() {
+        use futures::StreamExt;
+
+        // The trace of the headers alone overflows the stderr pipe.
+        let url = serve(|_| {
+            let headers: String = (0..2000)
+                .map(|i| format!("X-Filler-{}: {}\r\n", i, "x".repeat(40)))
+                .collect();
+            format!(
+                "HTTP/1.1 200 OK\r\n{}Content-Length: 4\r\nConnection: close\r\n\r\na\nb\n",
+                headers
+            )
+            .into_bytes()
+        })
+        .await;
+        let lines = Curl::new(&url)
+            .verbose(true)
+            .send_stream()
+            .map(Result::unwrap)
+            .collect::<Vec<String>>();
+        let lines = tokio::time::timeout(Duration::from_secs(10), lines)
+            .await
+            .unwrap();
+        assert_eq!(lines, ["a", "b"]);
+    }
+
+    #[test]
+    fn limit_rate() {
+        let args = Curl::new("https://httpbin.org/get")
+            .limit_rate("200K")
+            .build_args()
+            .unwrap();
+        assert!(args.windows(2).any(|w| w == ["--limit-rate", "200K"]));
+
+        for rate in ["abc", "", "10X", "1.5M"] {
+            let err = Curl::new("https://httpbin.org/get")
+                .limit_rate(rate)
+                .build_args()
+                .unwrap_err();
+            assert!(matches!(err, CurlError::InvalidInput(_)));
+        }
+    }
+
+    #[tokio::test]
+    async fn idle_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            read_request(&mut stream).await;
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\nabc")
+                .await
+                .unwrap();
+            tokio::time::sleep(Duration::from_secs(10)).await;
+        });
+
+        let start = std::time::Instant::now();
+        let err = Curl::new(&url)
+            .idle_timeout(Duration::from_millis(300))
+            .send()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CurlError::IdleTimeout(_)));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // A slow download to a file is still making progress.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            read_request(&mut stream).await;
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\n")
+                .await
+                .unwrap();
+            for byte in b"trickled" {
+                tokio::time::sleep(Duration::from_millis(150)).await;
+                stream.write_all(&[*byte]).await.unwrap();
+            }
+        });
+        let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+        let response = Curl::new(&url)
+            .output_file(path.to_str().unwrap())
+            .idle_timeout(Duration::from_millis(400))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "trickled");
+    }
+
+    #[tokio::test]
+    async fn body_bytes() {
+        // Responds with the debug representation of the request body.
+        let url = serve(|request| {
+            let (_, body) = request.split_once("\r\n\r\n").unwrap();
+            ok(&format!("{:?}", body))
+        })
+        .await;
+        let data = "line1\r\nline2\n\0end é";
+
+        let response = Curl::new(&url)
             .method(Method::POST)
-            .set_header("Content-Type: application/json")
-            .set_header("Cookie: test-cookie");
-        let response = curl.send().await.unwrap();
-        println!("status code: {:?}", response.status_code);
-        println!("body: {}", response.body);
-        println!("headers: {:?}", response.headers);
+            .body_bytes(data.as_bytes().to_vec())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.body, format!("{:?}", data));
+
+        let path = std::env::temp_dir().join(format!("curl-wrapper-body-{}", std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        let response = Curl::new(&url)
+            .method(Method::POST)
+            .body_from_file(path.to_str().unwrap())
+            .send()
+            .await
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(response.body, format!("{:?}", data));
     }
 
     #[tokio::test]
-    async fn put() {
-        let curl = Curl::new("https://httpbin.org/put")
+    async fn exit_code() {
+        let url = serve(|_| ok("Hello, World!")).await;
+        let response = Curl::new(&url).send().await.unwrap();
+        assert_eq!(response.exit_code, 0);
+
+        // The body is cut short of its announced length.
+        let url =
+            serve(|_| b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\npartial".to_vec()).await;
+        let err = Curl::new(&url).send().await.unwrap_err();
+        let CurlError::Curl {
+            exit_code: Some(18),
+            response: Some(response),
+            ..
+        } = err
+        else {
+            panic!("unexpected error: {:?}", err);
+        };
+        assert_eq!(response.exit_code, 18);
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, "partial");
+
+        let err = Curl::new("http://127.0.0.1:1/").send().await.unwrap_err();
+        assert!(matches!(err, CurlError::Curl { response: None, .. }));
+    }
+
+    #[tokio::test]
+    async fn body_binary() {
+        let url = serve(|request| {
+            let (_, body) = request.split_once("\r\n\r\n").unwrap();
+            ok(&format!("{:?}", body))
+        })
+        .await;
+        let response = Curl::new(&url)
+            .method(Method::POST)
+            .set_body_binary("line1\nline2")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.body, r#""line1\nline2""#);
+
+        let response = Curl::new(&url)
+            .method(Method::POST)
+            .set_body_binary("@not-a-file")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.body, r#""@not-a-file""#);
+    }
+
+    #[test]
+    fn user_agent() {
+        let args = Curl::new("https://httpbin.org/get")
+            .set_header("user-agent: curl/7.81.0")
+            .set_header("Accept: application/json")
+            .user_agent("my-app/1.0")
+            .build_args()
+            .unwrap();
+        assert!(args.windows(2).any(|w| w == ["-A", "my-app/1.0"]));
+        assert!(!args
+            .iter()
+            .any(|a| a.to_lowercase().starts_with("user-agent:")));
+        assert!(args.contains(&"Accept: application/json".to_string()));
+    }
+
+    #[tokio::test]
+    async fn content_length() {
+        let url = serve(|request| echo(&request)).await;
+        let body = "héllo, wörld";
+        let response = Curl::new(&url)
+            .method(Method::POST)
+            .set_body(body)
+            .send()
+            .await
+            .unwrap();
+        assert!(response
+            .body
+            .contains(&format!("Content-Length: {}\n", body.len())));
+
+        let response = Curl::new(&url)
             .method(Method::PUT)
-            .set_header("Content-Type: application/json")
-            .set_header("Cookie: test-cookie");
+            .body_bytes(vec![0; 300])
+            .send()
+            .await
+            .unwrap();
+        assert!(response.body.contains("Content-Length: 300\n"));
+        assert!(!response.body.contains("Transfer-Encoding"));
+
+        // The redirected request has no body, so no Content-Length either.
+        let url = serve(|request| {
+            if request.starts_with("POST /a ") {
+                return b"HTTP/1.1 303 See Other\r\nLocation: /b\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_vec();
+            }
+            echo(&request)
+        })
+        .await;
+        let response = Curl::new(&format!("{}/a", url))
+            .method(Method::POST)
+            .set_body("hello")
+            .redirects(true)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 200);
+        assert!(response.body.starts_with("POST /b HTTP/1.1"));
+        assert!(!response.body.contains("Content-Length"));
+
+        let args = Curl::new(&url)
+            .set_header("content-length: 3")
+            .set_body("abc")
+            .build_args()
+            .unwrap();
+        assert_eq!(
+            args.iter()
+                .filter(|a| a.to_lowercase().starts_with("content-length"))
+                .count(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8() {
+        let url = serve(|_| {
+            let mut response = b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\n".to_vec();
+            response.extend_from_slice(b"ab\xffc\xfe!");
+            response
+        })
+        .await;
+
+        let response = Curl::new(&url).send().await.unwrap();
+        assert_eq!(response.body, "ab\u{fffd}c\u{fffd}!");
+
+        let response = Curl::new(&url)
+            .on_invalid_utf8(InvalidUtf8Strategy::Strip)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.body, "abc!");
+        assert_eq!(response.body_bytes, b"ab\xffc\xfe!");
+
+        let err = Curl::new(&url)
+            .on_invalid_utf8(InvalidUtf8Strategy::Error)
+            .send()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CurlError::InvalidResponse(_)));
+    }
+
+    #[test]
+    fn stats() {
+        let mut stats = Stats::new();
+        for ms in 1..=100 {
+            let output = format!(
+                "HTTP/1.1 200 OK\r\n\r\n{}time_namelookup=0\ntime_connect=0\ntime_appconnect=0\ntime_total={}\n",
+                WRITE_OUT_MARKER,
+                ms as f64 / 1000.0
+            );
+            stats.record(&CurlResponse::new(output.into_bytes()));
+        }
+        stats.record(&CurlResponse::new(b"HTTP/1.1 200 OK\r\n\r\n".to_vec()));
+
+        let summary = stats.summary().unwrap();
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.p50, Duration::from_millis(50));
+        assert_eq!(summary.p90, Duration::from_millis(90));
+        assert_eq!(summary.p99, Duration::from_millis(99));
+        assert!(summary
+            .to_string()
+            .contains("curl_request_duration_seconds{quantile=\"0.9\"} 0.09\n"));
+    }
+
+    #[tokio::test]
+    async fn effective_url() {
+        let url = serve(|request| {
+            if request.starts_with("GET /redirect") {
+                b"HTTP/1.1 302 Found\r\nLocation: /final\r\nContent-Length: 0\r\n\r\n".to_vec()
+            } else {
+                ok("done")
+            }
+        })
+        .await;
+        let response = Curl::new(&format!("{}/redirect", url))
+            .redirects(true)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, "done");
+        assert_eq!(response.effective_url, Some(format!("{}/final", url)));
+    }
+
+    #[test]
+    fn keep_alive() {
+        let args = Curl::new("https://httpbin.org/get")
+            .keep_alive(true)
+            .keep_alive_time(Duration::from_secs(30))
+            .build_args()
+            .unwrap();
+        assert!(args.contains(&"--keepalive".to_string()));
+        assert!(args.windows(2).any(|w| w == ["--keepalive-time", "30"]));
+
+        let args = Curl::new("https://httpbin.org/get")
+            .keep_alive(false)
+            .build_args()
+            .unwrap();
+        assert!(args.contains(&"--no-keepalive".to_string()));
+        assert!(!args.contains(&"--keepalive-time".to_string()));
+
+        let args = Curl::new("https://httpbin.org/get").build_args().unwrap();
+        assert!(!args.iter().any(|a| a.contains("keepalive")));
+    }
+
+    #[tokio::test]
+    async fn send_many() {
+        let url = serve(|request| {
+            let path = request.split(' ').nth(1).unwrap_or_default().to_string();
+            if path == "/fail" {
+                return b"HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n".to_vec();
+            }
+            ok(&path)
+        })
+        .await;
+        let base = Curl::new("").fail_on_error(true);
+        let urls = ["/one", "/fail", "/three"].map(|path| format!("{}{}", url, path));
+        let results = Curl::send_many(&base, &urls).await;
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().body, "/one");
+        assert!(matches!(
+            results[1],
+            Err(CurlError::Http { status_code: 500 })
+        ));
+        assert_eq!(results[2].as_ref().unwrap().body, "/three");
+    }
+
+    #[tokio::test]
+    async fn body_from_iter() {
+        let url = serve(|request| {
+            let (head, mut chunked) = request.split_once("\r\n\r\n").unwrap();
+            assert!(head.contains("Transfer-Encoding: chunked"));
+            let mut body = String::new();
+            loop {
+                let (size, rest) = chunked.split_once("\r\n").unwrap();
+                let size = usize::from_str_radix(size, 16).unwrap();
+                if size == 0 {
+                    break;
+                }
+                body.push_str(&rest[..size]);
+                chunked = &rest[size + 2..];
+            }
+            ok(&body)
+        })
+        .await;
+        let curl = Curl::new(&url)
+            .method(Method::POST)
+            .body_from_iter((0..100).map(|i| format!("chunk {}\n", i)));
         let response = curl.send().await.unwrap();
-        println!("status code: {:?}", response.status_code);
-        println!("body: {}", response.body);
-        println!("headers: {:?}", response.headers);
+        let expected: String = (0..100).map(|i| format!("chunk {}\n", i)).collect();
+        assert_eq!(response.body, expected.trim());
+
+        assert!(matches!(curl.send().await, Err(CurlError::InvalidInput(_))));
     }
 
     #[tokio::test]
-    async fn delete() {
-        let curl = Curl::new("https://httpbin.org/delete")
-            .method(Method::DELETE)
-            .set_header("Content-Type: application/json")
-            .set_header("Cookie: test-cookie");
+    async fn proxy_tunnel() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let connect = read_request(&mut stream).await;
+            if !connect.starts_with("CONNECT example.com:80 ") {
+                return;
+            }
+            // Not every proxy says `Connection established`.
+            stream.write_all(b"HTTP/1.1 200 OK\r\n\r\n").await.unwrap();
+            read_request(&mut stream).await;
+            stream
+                .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 9\r\n\r\nnot found")
+                .await
+                .unwrap();
+        });
+        let response = Curl::new("http://example.com/")
+            .set_proxy(&proxy)
+            .raw_arg("--proxytunnel")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 404);
+        assert_eq!(response.headers, ["Content-Length: 9"]);
+        assert_eq!(response.body, "not found");
+
+        // A body that happens to look like a response isn't parsed.
+        let output = b"HTTP/1.1 200 OK\r\nContent-Length: 19\r\n\r\nHTTP/1.1 404 Nope\r\n\r\n";
+        let response = CurlResponse::new(output.to_vec());
+        assert_eq!(response.status_code, 200);
+
+        // Nor is a chunked or close-delimited one, which has no Content-Length.
+        let output =
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nHTTP/1.1 404 Nope\r\n\r\n";
+        let response = CurlResponse::new(output.to_vec());
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, "HTTP/1.1 404 Nope");
+    }
+
+    #[test]
+    fn reason() {
+        let response = CurlResponse::new(b"HTTP/1.1 404 Not Found\r\n\r\n".to_vec());
+        assert_eq!(response.status_code, 404);
+        assert_eq!(response.reason, "Not Found");
+
+        let response = CurlResponse::new(b"HTTP/2 200\r\nserver: test\r\n\r\n".to_vec());
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.reason, "");
+    }
+
+    #[test]
+    fn http2_headers() {
+        let output = b"HTTP/2 301\r\n:status: 301\r\nlocation: /next\r\n\r\nHTTP/2 200\r\n:status: 200\r\ncontent-type: text/plain\r\ncontent-length: 5\r\n\r\nhello";
+        let response = CurlResponse::new(output.to_vec());
+        assert_eq!(response.status_code, 200);
+        assert_eq!(
+            response.headers,
+            ["content-type: text/plain", "content-length: 5"]
+        );
+        assert_eq!(response.body, "hello");
+    }
+
+    #[test]
+    fn alpn_protocol() {
+        let mut response = CurlResponse::new(b"HTTP/1.1 200 OK\r\n\r\n".to_vec());
+        assert_eq!(response.alpn_protocol(), None);
+        response.trace = Some(
+            "* ALPN, offering h2\n* ALPN, offering http/1.1\n* ALPN, server accepted to use http/1.1\n"
+                .to_string(),
+        );
+        assert_eq!(response.alpn_protocol().as_deref(), Some("http/1.1"));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires network access to an HTTP/2 host"]
+    async fn alpn_protocol_h2() {
+        let response = Curl::new("https://www.google.com")
+            .verbose(true)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.alpn_protocol().as_deref(), Some("h2"));
+    }
+
+    #[test]
+    fn joined_header() {
+        let output = b"HTTP/1.1 200 OK\r\nCache-Control: no-cache\r\nSet-Cookie: a=1\r\ncache-control: max-age=0\r\nSet-Cookie: b=2\r\n\r\n";
+        let response = CurlResponse::new(output.to_vec());
+        assert_eq!(
+            response.joined_header("Cache-Control").as_deref(),
+            Some("no-cache, max-age=0")
+        );
+        assert_eq!(response.joined_header("Set-Cookie"), None);
+        assert_eq!(response.headers_all("set-cookie"), ["a=1", "b=2"]);
+        assert_eq!(response.joined_header("Expires"), None);
+        assert!(response.headers_all("Expires").is_empty());
+    }
+
+    #[test]
+    fn status_predicates() {
+        let status = |code: u16| {
+            let output = format!("HTTP/1.1 {} Status\r\n\r\n", code);
+            let response = CurlResponse::new(output.into_bytes());
+            [
+                response.is_success(),
+                response.is_redirect(),
+                response.is_client_error(),
+                response.is_server_error(),
+            ]
+        };
+        assert_eq!(status(200), [true, false, false, false]);
+        assert_eq!(status(299), [true, false, false, false]);
+        assert_eq!(status(301), [false, true, false, false]);
+        assert_eq!(status(404), [false, false, true, false]);
+        assert_eq!(status(503), [false, false, false, true]);
+        assert_eq!(status(600), [false, false, false, false]);
+        assert!(!CurlResponse::new(Vec::new()).is_success());
+    }
+
+    #[test]
+    fn cache_policy() {
+        let output = b"HTTP/1.1 200 OK\r\nCache-Control: max-age=300\r\nAge: 60\r\n\r\n";
+        let policy = CurlResponse::new(output.to_vec()).cache_policy();
+        assert!(policy.is_cacheable());
+        assert_eq!(policy.remaining_freshness(), Some(Duration::from_secs(240)));
+
+        let output = b"HTTP/1.1 200 OK\r\nDate: Sun, 06 Nov 1994 08:49:37 GMT\r\nExpires: Sun, 06 Nov 1994 08:59:37 GMT\r\n\r\n";
+        let policy = CurlResponse::new(output.to_vec()).cache_policy();
+        assert_eq!(policy.remaining_freshness(), Some(Duration::from_secs(600)));
+
+        let output = b"HTTP/1.1 200 OK\r\nCache-Control: private, no-store\r\nExpires: 0\r\n\r\n";
+        let policy = CurlResponse::new(output.to_vec()).cache_policy();
+        assert!(policy.private);
+        assert!(!policy.is_cacheable());
+        assert_eq!(policy.remaining_freshness(), Some(Duration::ZERO));
+
+        let output = b"HTTP/1.1 200 OK\r\nCache-Control: no-cache, max-age=60\r\n\r\n";
+        let policy = CurlResponse::new(output.to_vec()).cache_policy();
+        assert!(policy.is_cacheable());
+        assert_eq!(policy.remaining_freshness(), Some(Duration::ZERO));
+
+        let output = b"HTTP/1.1 200 OK\r\n\r\n";
+        assert_eq!(
+            CurlResponse::new(output.to_vec())
+                .cache_policy()
+                .remaining_freshness(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn send_all_limited() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        tokio::spawn({
+            let (active, peak) = (active.clone(), peak.clone());
+            async move {
+                while let Ok((mut stream, _)) = listener.accept().await {
+                    let (active, peak) = (active.clone(), peak.clone());
+                    tokio::spawn(async move {
+                        let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        let request = read_request(&mut stream).await;
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        let path = request.split(' ').nth(1).unwrap_or_default().to_string();
+                        active.fetch_sub(1, Ordering::SeqCst);
+                        let _ = stream.write_all(&ok(&path)).await;
+                    });
+                }
+            }
+        });
+
+        let requests = (0..10)
+            .map(|i| Curl::new(&format!("{}/{}", url, i)))
+            .collect();
+        let results = Curl::send_all_limited(requests, 2).await.unwrap();
+        let bodies: Vec<String> = results.into_iter().map(|r| r.unwrap().body).collect();
+        let expected: Vec<String> = (0..10).map(|i| format!("/{}", i)).collect();
+        assert_eq!(bodies, expected);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+
+        let err = Curl::send_all_limited(vec![Curl::new(&url)], 0)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CurlError::InvalidInput(_)));
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn cassette() {
+        let hits = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let url = serve({
+            let hits = hits.clone();
+            move |_| {
+                hits.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                ok("recorded")
+            }
+        })
+        .await;
+        let path =
+            std::env::temp_dir().join(format!("curl-wrapper-cassette-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let response = Curl::new(&url).cassette(path).send().await.unwrap();
+        assert_eq!(response.body, "recorded");
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        // curl can't be spawned, so the response must come from the cassette.
+        let response = Curl::new(&url)
+            .cassette(path)
+            .curl_path("/nonexistent/curl")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.body, "recorded");
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+        let err = Curl::new(&url)
+            .method(Method::POST)
+            .cassette(path)
+            .curl_path("/nonexistent/curl")
+            .send()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CurlError::Io(_)));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn from_template_file() {
+        let path =
+            std::env::temp_dir().join(format!("curl-wrapper-template-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            r#"{
+                "url": "https://example.com/items",
+                "method": "POST",
+                "headers": ["Content-Type: application/json"],
+                "body": "{\"name\": \"item\"}"
+            }"#,
+        )
+        .unwrap();
+        let curl = Curl::from_template_file(path).unwrap();
+        assert_eq!(curl.url, "https://example.com/items");
+        assert_eq!(curl.method, Some(Method::POST));
+        assert_eq!(curl.headers, ["Content-Type: application/json"]);
+        assert!(matches!(&curl.body, Some(Body::Text(body)) if body == r#"{"name": "item"}"#));
+
+        std::fs::write(path, r#"{"method": "GET"}"#).unwrap();
+        let err = Curl::from_template_file(path).unwrap_err();
+        assert!(matches!(err, CurlError::InvalidInput(_)));
+        std::fs::write(path, r#"{"url": 1}"#).unwrap();
+        let err = Curl::from_template_file(path).unwrap_err();
+        assert!(matches!(err, CurlError::InvalidInput(_)));
+        std::fs::write(
+            path,
+            r#"{"url": "https://example.com", "curl_path": "/bin/echo", "raw_args": ["pwned"]}"#,
+        )
+        .unwrap();
+        let err = Curl::from_template_file(path).unwrap_err();
+        assert!(matches!(err, CurlError::InvalidInput(_)));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn snapshot() {
+        let curl = Curl::new("https://httpbin.org/post")
+            .method(Method::POST)
+            .set_header("Accept: application/json")
+            .body_bytes(vec![0, 1, 2])
+            .redirects(true)
+            .keep_alive_time(Duration::from_secs(30))
+            .on_invalid_utf8(InvalidUtf8Strategy::Strip)
+            .cookie("a", "1");
+        let json = serde_json::to_string(&curl.snapshot()).unwrap();
+        let restored = Curl::from_spec(serde_json::from_str(&json).unwrap());
+        assert_eq!(restored.snapshot(), curl.snapshot());
+        assert_eq!(restored.build_args().unwrap(), curl.build_args().unwrap());
+
+        let spec: RequestSpec = serde_json::from_str(r#"{"url": "https://example.com"}"#).unwrap();
+        assert_eq!(spec.url, "https://example.com");
+        assert_eq!(spec.curl_path, Curl::new("").snapshot().curl_path);
+    }
+
+    #[test]
+    fn url_resolver() {
+        let curl = Curl::new("service://users/profile")
+            .url_resolver(|url| url.replace("service://users", "http://10.0.0.7:8080"));
+        let args = curl.build_args().unwrap();
+        assert!(args.contains(&"http://10.0.0.7:8080/profile".to_string()));
+        assert!(!args.iter().any(|arg| arg.starts_with("service://")));
+
+        let curl = Curl::new("https://example.com")
+            .safe_mode(true)
+            .url_resolver(|url| format!("{}\r\nX-Injected: 1", url));
+        assert!(matches!(curl.build_args(), Err(CurlError::InvalidInput(_))));
+    }
+
+    #[tokio::test]
+    async fn allow_http() {
+        let url = serve(|_| ok("done")).await;
+        let err = Curl::new(&url).allow_http(false).send().await.unwrap_err();
+        assert!(matches!(err, CurlError::InvalidInput(_)));
+        let response = Curl::new(&url).allow_http(true).send().await.unwrap();
+        assert_eq!(response.body, "done");
+
+        let args = Curl::new("https://example.com")
+            .allow_http(false)
+            .build_args()
+            .unwrap();
+        assert!(args.windows(2).any(|w| w == ["--proto-redir", "=https"]));
+    }
+
+    #[test]
+    fn with_defaults() {
+        let api = Curl::with_defaults(vec!["Accept: application/json", "User-Agent: my-app/1.0"]);
+        let args = api.new("https://example.com").build_args().unwrap();
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["-H", "Accept: application/json"]));
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["-H", "User-Agent: my-app/1.0"]));
+
+        let args = api
+            .new("https://example.com")
+            .set_header("accept: image/png")
+            .set_header("X-Request-Id: 1")
+            .build_args()
+            .unwrap();
+        let headers: Vec<&String> = args
+            .windows(2)
+            .filter(|w| w[0] == "-H")
+            .map(|w| &w[1])
+            .collect();
+        assert_eq!(
+            headers,
+            [
+                "User-Agent: my-app/1.0",
+                "accept: image/png",
+                "X-Request-Id: 1"
+            ]
+        );
+    }
+
+    #[test]
+    fn raw_args() {
+        let curl = Curl::new("https://example.com")
+            .raw_arg("--tlsv1.3")
+            .raw_args(vec!["--retry", "3"])
+            .raw_arg("a b; rm -rf /");
+        let args = curl.build_args().unwrap();
+        let url = args
+            .iter()
+            .position(|arg| arg == "https://example.com")
+            .unwrap();
+        assert_eq!(
+            args[url - 5..url],
+            ["--tlsv1.3", "--retry", "3", "a b; rm -rf /", "--url"]
+        );
+        assert!(curl
+            .to_command_string()
+            .unwrap()
+            .contains("'a b; rm -rf /'"));
+    }
+
+    #[test]
+    fn tcp_options() {
+        let flags = |fastopen: bool, nodelay: Option<bool>| {
+            let mut curl = Curl::new("https://example.com").tcp_fastopen(fastopen);
+            if let Some(nodelay) = nodelay {
+                curl = curl.tcp_nodelay(nodelay);
+            }
+            curl.build_args()
+                .unwrap()
+                .into_iter()
+                .filter(|arg| arg.contains("tcp"))
+                .collect::<Vec<_>>()
+        };
+        assert!(flags(false, None).is_empty());
+        assert_eq!(flags(true, None), ["--tcp-fastopen"]);
+        assert_eq!(flags(false, Some(true)), ["--tcp-nodelay"]);
+        assert_eq!(flags(false, Some(false)), ["--no-tcp-nodelay"]);
+        assert_eq!(
+            flags(true, Some(false)),
+            ["--tcp-fastopen", "--no-tcp-nodelay"]
+        );
+    }
+
+    #[tokio::test]
+    async fn max_response_bytes() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (closed_tx, closed_rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            read_request(&mut stream).await;
+            let head = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+            stream.write_all(head).await.unwrap();
+            let chunk = format!("4000\r\n{}\r\n", "x".repeat(0x4000));
+            // Streams forever, until curl is killed.
+            while stream.write_all(chunk.as_bytes()).await.is_ok() {}
+            let _ = closed_tx.send(());
+        });
+
+        let err = Curl::new(&url)
+            .max_response_bytes(1024 * 1024)
+            .send()
+            .await
+            .unwrap_err();
+        assert!(matches!(err, CurlError::ResponseTooLarge(1048576)));
+        tokio::time::timeout(Duration::from_secs(5), closed_rx)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test]
+    fn from_env() {
+        const CA: &str = "/tmp/curl-wrapper-from-env-ca.pem";
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        let curl = Curl::from_env_with("https://example.com", env(&[("CURL_CA_BUNDLE", CA)]));
+        let args = curl.build_args().unwrap();
+        assert!(args.windows(2).any(|w| w == ["--cacert", CA]));
+
+        let args = Curl::from_env_with("https://example.com", env(&[("CURL_CA_BUNDLE", CA)]))
+            .cacert("/etc/ca.pem")
+            .build_args()
+            .unwrap();
+        assert!(args.windows(2).any(|w| w == ["--cacert", "/etc/ca.pem"]));
+        assert!(!args.contains(&CA.to_string()));
+
+        let vars = env(&[
+            ("HTTP_PROXY", "http://attacker:8080"),
+            ("HTTPS_PROXY", "http://proxy:3128"),
+        ]);
+        let args = Curl::from_env_with("http://example.com", vars)
+            .build_args()
+            .unwrap();
+        assert!(!args.contains(&"--proxy".to_string()));
+        let args = Curl::from_env_with("https://example.com", vars)
+            .build_args()
+            .unwrap();
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--proxy", "http://proxy:3128"]));
+        let vars = env(&[("http_proxy", "http://proxy:3128")]);
+        let args = Curl::from_env_with("http://example.com", vars)
+            .build_args()
+            .unwrap();
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--proxy", "http://proxy:3128"]));
+
+        // Explicit proxies replace the one from the environment.
+        let vars = env(&[
+            ("HTTPS_PROXY", "http://proxy:3128"),
+            ("NO_PROXY", "localhost"),
+        ]);
+        let args = Curl::from_env_with("https://example.com", vars)
+            .socks5("socks.example.com:1080")
+            .build_args()
+            .unwrap();
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--socks5", "socks.example.com:1080"]));
+        assert!(!args.contains(&"--proxy".to_string()));
+        let args = Curl::from_env_with("https://example.com", vars)
+            .set_proxy("http://other:8080")
+            .no_proxy("example.org")
+            .build_args()
+            .unwrap();
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--proxy", "http://other:8080"]));
+        assert!(args.windows(2).any(|w| w == ["--noproxy", "example.org"]));
+    }
+
+    #[test]
+    fn expect_continue() {
+        let curl = Curl::new("https://example.com").body_bytes(vec![0; 1024 * 1024]);
+        let args = curl.build_args().unwrap();
+        assert!(!args.contains(&"Expect:".to_string()));
+
+        let args = curl.expect_continue(false).build_args().unwrap();
+        assert!(args.windows(2).any(|w| w == ["-H", "Expect:"]));
+    }
+
+    #[tokio::test]
+    async fn create_dirs() {
+        let url = serve(|_| ok("hello")).await;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a/b/out.txt");
+        let curl = Curl::new(&url).output_file(path.to_str().unwrap());
+        assert!(curl.send().await.is_err());
+
+        curl.create_dirs(true).send().await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello");
+    }
+
+    #[tokio::test]
+    async fn output_file() {
+        let url = serve(|_| ok("hello")).await;
+        let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+        let curl = Curl::new(&url).output_file(path.to_str().unwrap());
+        let args = curl.build_args().unwrap();
+        assert!(!args.contains(&"--include".to_string()));
+        assert!(args.windows(2).any(|w| w == ["-D", "-"]));
+
         let response = curl.send().await.unwrap();
-        println!("status code: {:?}", response.status_code);
-        println!("body: {}", response.body);
-        println!("headers: {:?}", response.headers);
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.joined_header("Content-Length").unwrap(), "5");
+        assert!(response.body.is_empty());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "hello");
+
+        let args = Curl::new(&url).silent(false).build_args().unwrap();
+        assert!(!args.contains(&"--silent".to_string()));
+        assert!(args.contains(&"--include".to_string()));
     }
 
     #[tokio::test]
-    async fn redirect() {
-        let curl = Curl::new("https://httpbin.org/redirect-to?url=https://httpbin.org/get")
-            .set_header("Content-Type: application/json")
-            .redirects(true);
+    async fn byte_counters() {
+        let url = serve(|_| ok("0123456789")).await;
+        let body = "x".repeat(4096);
+        let response = Curl::new(&url)
+            .method(Method::POST)
+            .set_body(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.bytes_sent, 4096);
+        assert_eq!(response.bytes_received, 10);
+        assert_eq!(response.body, "0123456789");
+
+        let response = CurlResponse::new(b"HTTP/1.1 200 OK\r\n\r\nhello".to_vec());
+        assert_eq!((response.bytes_sent, response.bytes_received), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn repeated_headers() {
+        let url = serve(|request| echo(&request)).await;
+        let curl = Curl::new(&url)
+            .set_header("X-Forwarded-For: 10.0.0.1")
+            .set_headers(vec!["Accept: */*", "X-Forwarded-For: 10.0.0.2"]);
         let response = curl.send().await.unwrap();
-        println!("status code: {:?}", response.status_code);
-        println!("body: {}", response.body);
-        println!("headers: {:?}", response.headers);
+        let forwarded: Vec<&str> = response
+            .body
+            .lines()
+            .filter(|line| line.starts_with("X-Forwarded-For"))
+            .collect();
+        assert_eq!(
+            forwarded,
+            ["X-Forwarded-For: 10.0.0.1", "X-Forwarded-For: 10.0.0.2"]
+        );
+
+        let response = curl
+            .replace_header("x-forwarded-for", "10.0.0.3")
+            .send()
+            .await
+            .unwrap();
+        let forwarded: Vec<&str> = response
+            .body
+            .lines()
+            .filter(|line| line.to_ascii_lowercase().starts_with("x-forwarded-for"))
+            .collect();
+        assert_eq!(forwarded, ["x-forwarded-for: 10.0.0.3"]);
+    }
+
+    #[tokio::test]
+    async fn head() {
+        let url = serve(|request| {
+            if !request.starts_with("HEAD / ") {
+                return b"HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\n\r\n".to_vec();
+            }
+            b"HTTP/1.1 200 OK\r\nContent-Length: 1024\r\n\r\n".to_vec()
+        })
+        .await;
+        let curl = Curl::new(&url);
+        let response = curl.head().await.unwrap();
+        assert_eq!(response.status_code, 200);
+        assert_eq!(response.joined_header("Content-Length").unwrap(), "1024");
+        assert!(response.body.is_empty());
+
+        let args = curl.method(Method::HEAD).build_args().unwrap();
+        assert!(args.contains(&"--head".to_string()));
+        assert!(!args.contains(&"-X".to_string()));
+    }
+
+    #[test]
+    fn history() {
+        let output = b"HTTP/1.1 301 Moved Permanently\r\nLocation: /new\r\n\r\nHTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        let response = CurlResponse::new(output.to_vec());
+        assert_eq!(response.history.len(), 2);
+        assert_eq!(response.history[0].0, 301);
+        assert_eq!(response.history[0].1, ["Location: /new"]);
+        assert_eq!(response.history[1], (200, response.headers.clone()));
+        assert_eq!(response.body, "ok");
+    }
+
+    #[test]
+    fn push_path() {
+        let curl = Curl::new("https://x.com")
+            .push_path("users")
+            .push_path("a b");
+        assert_eq!(curl.url, "https://x.com/users/a%20b");
+
+        let curl = Curl::new("https://x.com/api/?q=1").push_path("a/b");
+        assert_eq!(curl.url, "https://x.com/api/a%2Fb?q=1");
+
+        let curl = Curl::new("not a url").push_path("users");
+        assert_eq!(curl.url, "not a url");
+    }
+
+    #[test]
+    fn tls_versions() {
+        let args = Curl::new("https://example.com")
+            .tls_min(TlsVersion::Tls1_2)
+            .tls_max(TlsVersion::Tls1_3)
+            .build_args()
+            .unwrap();
+        assert!(args.contains(&"--tlsv1.2".to_string()));
+        assert!(args.windows(2).any(|w| w == ["--tls-max", "1.3"]));
+
+        let err = Curl::new("https://example.com")
+            .tls_min(TlsVersion::Tls1_3)
+            .tls_max(TlsVersion::Tls1_2)
+            .build_args()
+            .unwrap_err();
+        assert!(matches!(err, CurlError::InvalidInput(_)));
+    }
+
+    #[test]
+    fn tls_session_cache() {
+        let args = Curl::new("https://example.com")
+            .tls_session_cache("/tmp/sessions")
+            .build_args()
+            .unwrap();
+        assert!(args
+            .windows(2)
+            .any(|w| w == ["--ssl-sessions", "/tmp/sessions"]));
+    }
+
+    #[tokio::test]
+    #[ignore = "requires network access and curl 8.12 or later"]
+    async fn tls_session_cache_resumes() {
+        let path =
+            std::env::temp_dir().join(format!("curl-wrapper-sessions-{}", std::process::id()));
+        let curl = Curl::new("https://example.com")
+            .tls_session_cache(path.to_str().unwrap())
+            .with_timing(true);
+        let first = curl.send().await.unwrap().timing.unwrap();
+        let second = curl.send().await.unwrap().timing.unwrap();
+        let _ = std::fs::remove_file(&path);
+        let handshake = |timing: Timing| timing.appconnect.saturating_sub(timing.connect);
+        assert!(handshake(second) < handshake(first));
     }
 }
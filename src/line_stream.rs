@@ -0,0 +1,81 @@
+//! Streaming of the lines curl writes to stdout.
+
+use crate::{curl_error, split_write_out, CurlError};
+use futures::stream::{self, Stream};
+use std::io;
+use tempfile::NamedTempFile;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader, Lines};
+use tokio::process::{Child, ChildStdout};
+use tokio::task::JoinHandle;
+
+/// The state of a line stream.
+struct State {
+    child: Child,
+    lines: Lines<BufReader<ChildStdout>>,
+    stderr: JoinHandle<io::Result<Vec<u8>>>,
+    /// The cookie file curl reads, kept until the stream ends.
+    _jar: Option<NamedTempFile>,
+}
+
+/// Streams the lines written to the stdout of `child`, without their line
+/// endings.
+pub(crate) fn line_stream(
+    child: Result<(Child, Option<NamedTempFile>), CurlError>,
+) -> impl Stream<Item = Result<String, CurlError>> {
+    let state = child.map(|(mut child, jar)| {
+        let stdout = child.stdout.take().expect("stdout is piped");
+        State {
+            stderr: read_stderr(&mut child),
+            child,
+            lines: BufReader::new(stdout).lines(),
+            _jar: jar,
+        }
+    });
+    stream::unfold(Some(state), |state| async move {
+        let mut state = match state? {
+            Ok(state) => state,
+            Err(err) => return Some((Err(err), None)),
+        };
+        match state.lines.next_line().await {
+            Ok(Some(line)) => Some((Ok(line), Some(Ok(state)))),
+            Ok(None) => wait(&mut state.child, &mut state.stderr)
+                .await
+                .err()
+                .map(|err| (Err(err), None)),
+            Err(err) => Some((Err(err.into()), None)),
+        }
+    })
+}
+
+/// Reads the stderr of `child` in the background, so that a verbose trace
+/// can't fill the pipe and block curl while stdout is streamed.
+pub(crate) fn read_stderr(child: &mut Child) -> JoinHandle<io::Result<Vec<u8>>> {
+    let pipe = child.stderr.take();
+    tokio::spawn(async move {
+        let mut stderr = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut stderr).await?;
+        }
+        Ok(stderr)
+    })
+}
+
+/// Waits for curl to exit, returning an error if it failed.
+pub(crate) async fn wait(
+    child: &mut Child,
+    stderr: &mut JoinHandle<io::Result<Vec<u8>>>,
+) -> Result<(), CurlError> {
+    let status = child.wait().await?;
+    let stderr = stderr.await.map_err(io::Error::other)??;
+    let (stderr, write_out) = split_write_out(&stderr);
+    match status.code() {
+        Some(0) => Ok(()),
+        Some(22) => Err(CurlError::Http {
+            status_code: write_out
+                .get("http_code")
+                .and_then(|code| code.parse().ok())
+                .unwrap_or(0),
+        }),
+        exit_code => Err(curl_error(exit_code, stderr)),
+    }
+}
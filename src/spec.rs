@@ -0,0 +1,312 @@
+//! A serializable snapshot of a request's settings.
+
+#[cfg(feature = "json")]
+use crate::CurlError;
+use crate::{AuthMethod, Body, Curl, CurlBuilder, InvalidUtf8Strategy, Method, TlsVersion};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The settings of a `CurlBuilder`, which can be persisted with serde and
+/// restored with `Curl::from_spec`.
+///
+/// The deadline source, URL resolver, cookie store and a body set with
+/// `body_from_iter` aren't serializable and are left out. Missing fields take
+/// their default value when deserializing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestSpec {
+    pub curl_path: String,
+    pub url: String,
+    pub method: Option<Method>,
+    pub headers: Vec<String>,
+    pub default_headers: Vec<String>,
+    pub user_agent: Option<String>,
+    /// A body sent with `-d`, as set by `set_body`.
+    pub body: Option<String>,
+    /// A body sent byte for byte, as set by `body_bytes`.
+    pub body_bytes: Option<Vec<u8>>,
+    /// A body read from a file, as set by `body_from_file`.
+    pub body_file: Option<String>,
+    pub expect_continue: bool,
+    pub proxy: Option<String>,
+    pub no_proxy: Option<String>,
+    pub env_proxy: Option<String>,
+    pub env_no_proxy: Option<String>,
+    pub socks5: Option<String>,
+    pub socks5_hostname: Option<String>,
+    pub env: Vec<(String, String)>,
+    pub clear_proxy_env: bool,
+    pub redirects: bool,
+    pub allow_http: bool,
+    pub compressed: bool,
+    pub compression_algorithms: Vec<String>,
+    #[cfg(feature = "decode")]
+    pub capture_wire: bool,
+    pub fail_on_error: bool,
+    pub interface: Option<String>,
+    pub local_port: Option<String>,
+    pub keep_alive: Option<bool>,
+    pub keep_alive_time: Option<Duration>,
+    pub tls_session_cache: Option<String>,
+    pub tls_min: Option<TlsVersion>,
+    pub tls_max: Option<TlsVersion>,
+    pub cacert: Option<String>,
+    pub env_cacert: Option<String>,
+    pub tcp_fastopen: bool,
+    pub tcp_nodelay: Option<bool>,
+    pub unix_socket: Option<String>,
+    pub limit_rate: Option<String>,
+    pub max_size: Option<u64>,
+    pub buffer_limit: Option<usize>,
+    pub max_response_bytes: Option<usize>,
+    pub output_file: Option<String>,
+    pub create_dirs: bool,
+    pub silent: bool,
+    pub range: Option<String>,
+    pub resolve: Vec<String>,
+    pub block_private_ips: bool,
+    pub idle_timeout: Option<Duration>,
+    pub hedge: Option<Duration>,
+    pub timing: bool,
+    pub verbose: bool,
+    pub invalid_utf8: InvalidUtf8Strategy,
+    pub auth: Option<(AuthMethod, String, String)>,
+    pub strict_parse: bool,
+    pub cookies: Vec<String>,
+    pub cookie_jar: Option<String>,
+    pub cookie_file: Option<String>,
+    pub safe_mode: bool,
+    pub canonicalize_headers: bool,
+    pub ipv4: bool,
+    pub ipv6: bool,
+    pub raw_args: Vec<String>,
+    #[cfg(feature = "json")]
+    pub cassette: Option<String>,
+}
+
+/// A request definition read by `Curl::from_template_file`.
+#[cfg(feature = "json")]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RequestTemplate {
+    url: String,
+    #[serde(default)]
+    method: Option<Method>,
+    #[serde(default)]
+    headers: Vec<String>,
+    #[serde(default)]
+    body: Option<String>,
+}
+
+impl Default for RequestSpec {
+    fn default() -> Self {
+        Curl::new("").snapshot()
+    }
+}
+
+impl CurlBuilder {
+    /// Returns a serializable snapshot of the request's settings.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let spec = Curl::new("https://example.com")
+    ///     .set_header("Accept: application/json")
+    ///     .snapshot();
+    /// assert_eq!(spec.headers, ["Accept: application/json"]);
+    /// ```
+    pub fn snapshot(&self) -> RequestSpec {
+        let (body, body_bytes, body_file) = match &self.body {
+            Some(Body::Text(body)) => (Some(body.clone()), None, None),
+            Some(Body::Bytes(body)) => (None, Some(body.clone()), None),
+            Some(Body::File(path)) => (None, None, Some(path.clone())),
+            Some(Body::Chunks(_)) | None => (None, None, None),
+        };
+        RequestSpec {
+            curl_path: self.curl_path.clone(),
+            url: self.url.clone(),
+            method: self.method.clone(),
+            headers: self.headers.clone(),
+            default_headers: self.default_headers.clone(),
+            user_agent: self.user_agent.clone(),
+            body,
+            body_bytes,
+            body_file,
+            expect_continue: self.expect_continue,
+            proxy: self.proxy.clone(),
+            no_proxy: self.no_proxy.clone(),
+            env_proxy: self.env_proxy.clone(),
+            env_no_proxy: self.env_no_proxy.clone(),
+            socks5: self.socks5.clone(),
+            socks5_hostname: self.socks5_hostname.clone(),
+            env: self.env.clone(),
+            clear_proxy_env: self.clear_proxy_env,
+            redirects: self.redirects,
+            allow_http: self.allow_http,
+            compressed: self.compressed,
+            compression_algorithms: self.compression_algorithms.clone(),
+            #[cfg(feature = "decode")]
+            capture_wire: self.capture_wire,
+            fail_on_error: self.fail_on_error,
+            interface: self.interface.clone(),
+            local_port: self.local_port.clone(),
+            keep_alive: self.keep_alive,
+            keep_alive_time: self.keep_alive_time,
+            tls_session_cache: self.tls_session_cache.clone(),
+            tls_min: self.tls_min,
+            tls_max: self.tls_max,
+            cacert: self.cacert.clone(),
+            env_cacert: self.env_cacert.clone(),
+            tcp_fastopen: self.tcp_fastopen,
+            tcp_nodelay: self.tcp_nodelay,
+            unix_socket: self.unix_socket.clone(),
+            limit_rate: self.limit_rate.clone(),
+            max_size: self.max_size,
+            buffer_limit: self.buffer_limit,
+            max_response_bytes: self.max_response_bytes,
+            output_file: self.output_file.clone(),
+            create_dirs: self.create_dirs,
+            silent: self.silent,
+            range: self.range.clone(),
+            resolve: self.resolve.clone(),
+            block_private_ips: self.block_private_ips,
+            idle_timeout: self.idle_timeout,
+            hedge: self.hedge,
+            timing: self.timing,
+            verbose: self.verbose,
+            invalid_utf8: self.invalid_utf8,
+            auth: self.auth.clone(),
+            strict_parse: self.strict_parse,
+            cookies: self.cookies.clone(),
+            cookie_jar: self.cookie_jar.clone(),
+            cookie_file: self.cookie_file.clone(),
+            safe_mode: self.safe_mode,
+            canonicalize_headers: self.canonicalize_headers,
+            ipv4: self.ipv4,
+            ipv6: self.ipv6,
+            raw_args: self.raw_args.clone(),
+            #[cfg(feature = "json")]
+            cassette: self.cassette.clone(),
+        }
+    }
+}
+
+impl Curl {
+    /// Create a new `CurlBuilder` from a snapshot taken with `snapshot`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use curl_wrapper::Curl;
+    ///
+    /// let spec = Curl::new("https://example.com").redirects(true).snapshot();
+    /// let curl = Curl::from_spec(spec);
+    /// ```
+    pub fn from_spec(spec: RequestSpec) -> CurlBuilder {
+        let body = match (spec.body, spec.body_bytes, spec.body_file) {
+            (Some(body), _, _) => Some(Body::Text(body)),
+            (None, Some(body), _) => Some(Body::Bytes(body)),
+            (None, None, Some(path)) => Some(Body::File(path)),
+            (None, None, None) => None,
+        };
+        CurlBuilder {
+            curl_path: spec.curl_path,
+            url: spec.url,
+            method: spec.method,
+            headers: spec.headers,
+            default_headers: spec.default_headers,
+            user_agent: spec.user_agent,
+            body,
+            expect_continue: spec.expect_continue,
+            proxy: spec.proxy,
+            no_proxy: spec.no_proxy,
+            env_proxy: spec.env_proxy,
+            env_no_proxy: spec.env_no_proxy,
+            socks5: spec.socks5,
+            socks5_hostname: spec.socks5_hostname,
+            env: spec.env,
+            clear_proxy_env: spec.clear_proxy_env,
+            redirects: spec.redirects,
+            allow_http: spec.allow_http,
+            compressed: spec.compressed,
+            compression_algorithms: spec.compression_algorithms,
+            #[cfg(feature = "decode")]
+            capture_wire: spec.capture_wire,
+            fail_on_error: spec.fail_on_error,
+            interface: spec.interface,
+            local_port: spec.local_port,
+            keep_alive: spec.keep_alive,
+            keep_alive_time: spec.keep_alive_time,
+            tls_session_cache: spec.tls_session_cache,
+            tls_min: spec.tls_min,
+            tls_max: spec.tls_max,
+            cacert: spec.cacert,
+            env_cacert: spec.env_cacert,
+            tcp_fastopen: spec.tcp_fastopen,
+            tcp_nodelay: spec.tcp_nodelay,
+            unix_socket: spec.unix_socket,
+            limit_rate: spec.limit_rate,
+            max_size: spec.max_size,
+            buffer_limit: spec.buffer_limit,
+            max_response_bytes: spec.max_response_bytes,
+            output_file: spec.output_file,
+            create_dirs: spec.create_dirs,
+            silent: spec.silent,
+            range: spec.range,
+            resolve: spec.resolve,
+            block_private_ips: spec.block_private_ips,
+            idle_timeout: spec.idle_timeout,
+            hedge: spec.hedge,
+            timing: spec.timing,
+            verbose: spec.verbose,
+            invalid_utf8: spec.invalid_utf8,
+            auth: spec.auth,
+            strict_parse: spec.strict_parse,
+            cookies: spec.cookies,
+            cookie_jar: spec.cookie_jar,
+            cookie_file: spec.cookie_file,
+            safe_mode: spec.safe_mode,
+            canonicalize_headers: spec.canonicalize_headers,
+            ipv4: spec.ipv4,
+            ipv6: spec.ipv6,
+            raw_args: spec.raw_args,
+            #[cfg(feature = "json")]
+            cassette: spec.cassette,
+            ..Curl::new("")
+        }
+    }
+
+    /// Create a new `CurlBuilder` from the JSON request definition in the
+    /// file at `path`. It has a required `url`, and optional `method`,
+    /// `headers` and `body`; any other field is rejected, so a template can't
+    /// change how curl is run.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use curl_wrapper::Curl;
+    ///
+    /// // {"url": "https://example.com", "method": "POST", "headers": ["Accept: text/plain"], "body": "hi"}
+    /// let curl = Curl::from_template_file("requests/hello.json").unwrap();
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn from_template_file(path: &str) -> Result<CurlBuilder, CurlError> {
+        let json = std::fs::read(path)?;
+        let template: RequestTemplate = serde_json::from_slice(&json).map_err(|err| {
+            CurlError::InvalidInput(format!("invalid template {}: {}", path, err))
+        })?;
+        if template.url.is_empty() {
+            return Err(CurlError::InvalidInput(format!(
+                "template {} has no url",
+                path
+            )));
+        }
+        let mut curl = Curl::new(&template.url);
+        curl.method = template.method;
+        curl.headers = template.headers;
+        curl.body = template.body.map(Body::Text);
+        Ok(curl)
+    }
+}
@@ -0,0 +1,95 @@
+//! Timing statistics accumulated over many requests.
+
+use crate::CurlResponse;
+use std::fmt;
+use std::time::Duration;
+
+/// Accumulates the total time of requests sent with `with_timing(true)`.
+///
+/// # Example
+///
+/// ```
+/// use curl_wrapper::{CurlResponse, Stats};
+///
+/// let mut stats = Stats::new();
+/// let output = b"HTTP/1.1 200 OK\r\n\r\n";
+/// stats.record(&CurlResponse::new(output.to_vec()));
+/// assert!(stats.summary().is_none());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    totals: Vec<Duration>,
+}
+
+/// Percentiles of the total time of the recorded requests. Its `Display`
+/// output is an OpenMetrics summary.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    /// The number of recorded requests.
+    pub count: usize,
+    /// The sum of the total times.
+    pub sum: Duration,
+    /// The median total time.
+    pub p50: Duration,
+    /// The 90th percentile total time.
+    pub p90: Duration,
+    /// The 99th percentile total time.
+    pub p99: Duration,
+}
+
+impl Stats {
+    /// Create a new, empty `Stats`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the total time of `response`. Responses without timing
+    /// metrics are ignored.
+    pub fn record(&mut self, response: &CurlResponse) {
+        if let Some(timing) = &response.timing {
+            self.totals.push(timing.total);
+        }
+    }
+
+    /// Returns the summary of the recorded requests, or `None` if none was
+    /// recorded.
+    pub fn summary(&self) -> Option<Summary> {
+        if self.totals.is_empty() {
+            return None;
+        }
+        let mut totals = self.totals.clone();
+        totals.sort();
+        // Nearest-rank percentile.
+        let percentile = |p: usize| {
+            let rank = (p * totals.len()).div_ceil(100);
+            totals[rank.saturating_sub(1)]
+        };
+        Some(Summary {
+            count: totals.len(),
+            sum: totals.iter().sum(),
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+        })
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = "curl_request_duration_seconds";
+        writeln!(f, "# TYPE {} summary", name)?;
+        writeln!(f, "# UNIT {} seconds", name)?;
+        for (quantile, value) in [("0.5", self.p50), ("0.9", self.p90), ("0.99", self.p99)] {
+            writeln!(
+                f,
+                "{}{{quantile=\"{}\"}} {}",
+                name,
+                quantile,
+                value.as_secs_f64()
+            )?;
+        }
+        writeln!(f, "{}_sum {}", name, self.sum.as_secs_f64())?;
+        writeln!(f, "{}_count {}", name, self.count)?;
+        write!(f, "# EOF")
+    }
+}